@@ -0,0 +1,5 @@
+#!/bin/sh
+
+cat .code > program.s
+as program.s -o program.o
+ld -o program program.o
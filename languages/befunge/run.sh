@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.bf
-echo "$2" > .input
-shift 2
+cat .code > program.bf
 /opt/befungee/befungee.py program.bf "$@" < .input
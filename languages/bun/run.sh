@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.js
-echo "$2" > .input
-shift 2
+cat .code > program.js
 bun run program.js "$@" < .input
@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.c
+gcc program.c -o program
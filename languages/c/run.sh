@@ -1,7 +1,5 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.c
-gcc program.c -o program
-echo "$2" > .input
-shift 2
+# Legion compiles ahead of time when it caches compiled artifacts.
+[ -f .compiled ] || /bin/sh /var/run/compile.sh
 ./program "$@" < .input
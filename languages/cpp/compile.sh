@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.cc
+g++ program.cc -o program
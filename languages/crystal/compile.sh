@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.cr
+crystal build program.cr
@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.cs
+csc -nologo program.cs 2>/dev/null
@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.ts
-echo "$2" > .input
-shift 2
+cat .code > program.ts
 deno run -A program.ts "$@" < .input
@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.fs
+fsharpc --nologo --optimize- program.fs 2>/dev/null
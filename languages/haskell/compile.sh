@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.hs
+ghc -v0 -funfolding-use-threshold=16 -optc-O3 -o program program.hs
@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > Main.java
+javac Main.java
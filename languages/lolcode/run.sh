@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.lol
-echo "$2" > .input
-shift 2
+cat .code > program.lol
 lci program.lol "$@" < .input
@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.lua
-echo "$2" > .input
-shift 2
+cat .code > program.lua
 lua5.4 program.lua "$@" < .input
@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.pl
-echo "$2" > .input
-shift 2
+cat .code > program.pl
 perl program.pl "$@" < .input
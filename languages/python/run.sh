@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.py
-echo "$2" > .input
-shift 2
+cat .code > program.py
 python program.py "$@" < .input
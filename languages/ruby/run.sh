@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.rb
-echo "$2" > .input
-shift 2
+cat .code > program.rb
 ruby program.rb "$@" < .input
@@ -0,0 +1,4 @@
+#!/bin/sh
+
+cat .code > program.rs
+rustc -C opt-level=0 --color never program.rs
@@ -1,7 +1,5 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.rs
-echo "$2" > .input
-rustc -C opt-level=0 --color never program.rs
-shift 2
+# Legion compiles ahead of time when it caches compiled artifacts.
+[ -f .compiled ] || /bin/sh /var/run/compile.sh
 ./program "$@" < .input
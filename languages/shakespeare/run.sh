@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.spl
-echo "$2" > .input
-shift 2
+cat .code > program.spl
 shakespeare run program.spl "$@" < .input
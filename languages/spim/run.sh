@@ -1,6 +1,4 @@
 #!/bin/sh
 
-printf %s"\n" "$1" > program.s
-echo "$2" > .input
-shift 2
+cat .code > program.s
 /opt/spim/spim -file program.s "$@" < .input
@@ -0,0 +1,6 @@
+#!/bin/sh
+
+cat .code > program.ts
+tsc --lib DOM,ESNext --target ES2020 --strict --skipLibCheck --module commonjs \
+    --types /usr/local/share/.config/yarn/global/node_modules/@types/node \
+    program.ts
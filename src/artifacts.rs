@@ -0,0 +1,430 @@
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::config::Artifacts;
+use crate::docker::{exec, image_id, write_file};
+
+/// The script of a language that compiles the code of an eval, which only compiled languages have.
+pub const COMPILE_SCRIPT: &str = "/var/run/compile.sh";
+
+/// The user evals are compiled as ahead of their run. Programs run as another user, so that they
+/// cannot tamper with the output of a compilation before it is cached.
+pub const COMPILE_USER: &str = "1002:1002";
+
+/// Where compiled artifacts are kept in a container. Only the user legion runs its own commands as
+/// can write to it, so that evals cannot tamper with the artifacts of other evals.
+const ARTIFACTS_DIR: &str = "/tmp/artifacts";
+
+/// Where evals are compiled, each in a directory only [`COMPILE_USER`] can write to. The directory
+/// itself is sticky, so that no other user can replace the directory of a compilation.
+const COMPILE_DIR: &str = "/tmp/compile";
+
+/// Whether the compiled artifact of an eval was restored from the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restore {
+    /// The language is not compiled.
+    NotCompiled,
+    /// The artifact was copied into the eval directory.
+    Hit,
+    /// The code has to be compiled.
+    Miss,
+}
+
+/// Returns the key of the compiled artifact of `code` compiled with the environment variables
+/// `env`, or `None` when the image of the language is not built yet.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+pub async fn key(language: &str, code: &str, env: &[String]) -> Result<Option<String>> {
+    let Some(image) = image_id(language).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(digest(&image, code, env)))
+}
+
+/// Hashes the image id, the code and the environment variables into the key of an artifact.
+fn digest(image: &str, code: &str, env: &[String]) -> String {
+    // Variables like `CPATH` change what the compiler outputs, in whatever order they are given.
+    let mut env = env.iter().map(String::as_str).collect::<Vec<_>>();
+
+    env.sort_unstable();
+
+    let mut hasher = Sha256::new();
+
+    hasher.update(image.as_bytes());
+    hasher.update([0]);
+
+    // Every part is prefixed with its length, so that no code and environment hash alike.
+    for part in [code].into_iter().chain(env) {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Returns the directory the eval with the provided `id` is compiled in.
+#[must_use]
+pub fn compile_dir(id: &str) -> String {
+    format!("{}/{}", COMPILE_DIR, id)
+}
+
+/// Copies the compiled artifact cached under `key` into the directory of the eval with the
+/// provided `id`, marking it as compiled.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When copying the artifact fails.
+#[tracing::instrument]
+pub async fn restore(language: &str, key: &str, id: &str) -> Result<Restore> {
+    let output = exec(&[
+        "exec",
+        &format!("legion-{}", language),
+        "/bin/sh",
+        "-c",
+        "[ -f \"$0\" ] || { echo not-compiled; exit 0; }
+        [ -d \"$1\" ] || { echo miss; exit 0; }
+        cp -R \"$1/.\" \"$2\" && touch \"$1\" \"$2/.compiled\" && echo hit",
+        COMPILE_SCRIPT,
+        &format!("{}/{}", ARTIFACTS_DIR, key),
+        &format!("/tmp/eval/{}", id),
+    ])
+    .await?;
+
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "not-compiled" => Ok(Restore::NotCompiled),
+        "miss" => Ok(Restore::Miss),
+        "hit" => Ok(Restore::Hit),
+        _ => bail!(
+            "Restoring the artifact {} failed: {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+/// Makes sure the directories of the cache exist and are owned by the user legion runs its own
+/// commands as. Containers that were started before or outside of legion may lack them, and an
+/// eval may have created them to own them instead, in which case they are replaced.
+#[tracing::instrument]
+async fn create_dirs(language: &str) -> Result<()> {
+    let output = exec(&[
+        "exec",
+        &format!("legion-{}", language),
+        "/bin/sh",
+        "-c",
+        "set -e
+        for dir in \"$0:755\" \"$1:1777\"; do
+            path=${dir%:*} mode=${dir##*:}
+            if [ ! -d \"$path\" ] || [ -L \"$path\" ] || [ ! -O \"$path\" ]; then
+                rm -rf \"$path\"
+                mkdir -m \"$mode\" \"$path\"
+            fi
+        done",
+        ARTIFACTS_DIR,
+        COMPILE_DIR,
+    ])
+    .await?;
+
+    if !output.status.success() {
+        bail!(
+            "Creating the cache directories failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Creates the compile directory of the eval with the provided `id` and writes `code` into it,
+/// creating the directories of the cache first if they are missing.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When creating the directories or writing the code fails.
+#[tracing::instrument(skip(code))]
+pub async fn prepare(language: &str, id: &str, code: &str) -> Result<()> {
+    create_dirs(language).await?;
+
+    let dir = compile_dir(id);
+    let output = exec(&[
+        "exec",
+        &format!("-u{}", COMPILE_USER),
+        &format!("legion-{}", language),
+        "mkdir",
+        "-m",
+        "755",
+        &dir,
+    ])
+    .await?;
+
+    if !output.status.success() {
+        bail!(
+            "Creating the compile directory failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    write_file(language, COMPILE_USER, &format!("{}/.code", dir), format!("{}\n", code).as_bytes())
+        .await?;
+
+    Ok(())
+}
+
+/// Kills every process of [`COMPILE_USER`], including those that compile-time code left running
+/// in the background.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn kill_compilers(language: &str) -> Result<()> {
+    // Signalling every process the user may signal fails when there is none, which is fine.
+    exec(&[
+        "exec",
+        &format!("-u{}", COMPILE_USER),
+        &format!("legion-{}", language),
+        "/bin/sh",
+        "-c",
+        "kill -KILL -1",
+    ])
+    .await?;
+
+    Ok(())
+}
+
+/// Copies what the eval with the provided `id` compiled to into its eval directory, marking it
+/// as compiled so that its run script does not compile it again.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When copying the files fails.
+#[tracing::instrument]
+pub async fn copy_compiled(language: &str, id: &str) -> Result<()> {
+    let output = exec(&[
+        "exec",
+        &format!("legion-{}", language),
+        "/bin/sh",
+        "-c",
+        "cp -R \"$0\"/* \"$1\" && touch \"$1/.compiled\"",
+        &compile_dir(id),
+        &format!("/tmp/eval/{}", id),
+    ])
+    .await?;
+
+    if !output.status.success() {
+        bail!(
+            "Copying the compiled files failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes the compile directory of the eval with the provided `id`.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn remove_compile_dir(language: &str, id: &str) -> Result<()> {
+    exec(&[
+        "exec",
+        &format!("-u{}", COMPILE_USER),
+        &format!("legion-{}", language),
+        "rm",
+        "-rf",
+        &compile_dir(id),
+    ])
+    .await?;
+
+    Ok(())
+}
+
+/// Caches the files the eval with the provided `id` compiled to under `key`, then evicts the
+/// least recently used artifacts beyond the limits of `config`.
+///
+/// The files are only safe to cache once every process of [`COMPILE_USER`] is killed, since those
+/// could still change them.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When copying the artifact fails.
+#[tracing::instrument(skip(config))]
+pub async fn store(language: &str, key: &str, id: &str, config: &Artifacts) -> Result<()> {
+    let output = exec(&[
+        "exec",
+        &format!("legion-{}", language),
+        "/bin/sh",
+        "-c",
+        "set -e
+        staging=
+        trap 'rm -rf \"$staging\"' EXIT
+        cd \"$0\"
+        if [ ! -e \"$1\" ]; then
+            staging=$(mktemp -d .staging.XXXXXX)
+            cp -R \"$2\"/* \"$staging\"
+            mv \"$staging\" \"$1\"
+        fi
+        ls -t | tail -n +$(($3 + 1)) | xargs -r rm -rf
+        while [ \"$(du -sm . | cut -f1)\" -gt \"$4\" ] && [ -n \"$(ls)\" ]; do
+            rm -rf \"$(ls -t | tail -n 1)\"
+        done",
+        ARTIFACTS_DIR,
+        key,
+        &compile_dir(id),
+        &config.max_entries.to_string(),
+        &config.max_size.to_string(),
+    ])
+    .await?;
+
+    if !output.status.success() {
+        bail!(
+            "Caching the artifact {} failed: {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use nanoid::nanoid;
+    use tokio::time::{sleep, Duration};
+
+    use super::{
+        compile_dir,
+        digest,
+        prepare,
+        restore,
+        store,
+        Restore,
+        ARTIFACTS_DIR,
+        COMPILE_DIR,
+        COMPILE_USER,
+    };
+    use crate::config::{Artifacts, Language};
+    use crate::docker::{exec, prepare_containers, write_file};
+
+    /// Starts the bash container, pretending that bash is compiled.
+    async fn start() {
+        prepare_containers(&[String::from("bash")], &Language::default())
+            .await
+            .expect("Failed preparing containers.");
+
+        exec(&["exec", "-u0", "legion-bash", "touch", super::COMPILE_SCRIPT])
+            .await
+            .expect("Failed creating the compile script");
+    }
+
+    /// Compiles an eval to `program` with the provided `contents`, returning its id.
+    async fn compile(contents: &[u8]) -> String {
+        let id = nanoid!();
+
+        prepare("bash", &id, "echo").await.unwrap();
+        write_file("bash", COMPILE_USER, &format!("{}/program", compile_dir(&id)), contents)
+            .await
+            .unwrap();
+
+        id
+    }
+
+    async fn sh(script: &str) -> String {
+        let output = exec(&["exec", "legion-bash", "/bin/sh", "-c", script]).await.unwrap();
+
+        String::from_utf8_lossy(&output.stdout).trim().to_owned()
+    }
+
+    #[test]
+    fn keys_cover_the_environment() {
+        let env = |vars: &[&str]| vars.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let key = digest("image", "code", &env(&["A=1", "CPATH=/tmp"]));
+
+        assert_eq!(key, digest("image", "code", &env(&["CPATH=/tmp", "A=1"])));
+        assert_ne!(key, digest("image", "code", &env(&["A=1"])));
+        assert_ne!(key, digest("image", "code", &env(&["A=1", "CPATH=/home"])));
+        assert_ne!(digest("image", "code\0A=1", &[]), digest("image", "code", &env(&["A=1"])));
+    }
+
+    #[tokio::test]
+    async fn artifacts_are_stored_and_restored() {
+        start().await;
+
+        let key = nanoid!();
+        let config = Artifacts::default();
+        let compiled = compile(b"compiled").await;
+
+        assert_eq!(restore("bash", &key, &compiled).await.unwrap(), Restore::Miss);
+
+        store("bash", &key, &compiled, &config).await.unwrap();
+
+        let id = nanoid!();
+
+        sh(&format!("mkdir -p /tmp/eval/{}", id)).await;
+
+        assert_eq!(restore("bash", &key, &id).await.unwrap(), Restore::Hit);
+        assert_eq!(sh(&format!("cat /tmp/eval/{}/program", id)).await, "compiled");
+        assert_eq!(sh(&format!("[ -f /tmp/eval/{}/.compiled ] && echo yes", id)).await, "yes");
+
+        exec(&["kill", "legion-bash"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn artifacts_are_evicted_beyond_the_limits() {
+        start().await;
+        sh(&format!("rm -rf {}/*", ARTIFACTS_DIR)).await;
+
+        let config = Artifacts {
+            enabled: true,
+            max_entries: 2,
+            max_size: 1,
+        };
+
+        for key in ["first", "second", "third"] {
+            let id = compile(key.as_bytes()).await;
+
+            store("bash", key, &id, &config).await.unwrap();
+
+            // Eviction goes by modification time.
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(sh(&format!("ls {}", ARTIFACTS_DIR)).await, "second\nthird");
+
+        let id = compile(&vec![0; 2 * 1024 * 1024]).await;
+
+        store("bash", "large", &id, &config).await.unwrap();
+
+        assert!(!sh(&format!("ls {}", ARTIFACTS_DIR)).await.contains("large"));
+
+        exec(&["kill", "legion-bash"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cache_dirs_are_replaced_when_an_eval_owns_them() {
+        start().await;
+        sh(&format!("rm -rf {} {}", ARTIFACTS_DIR, COMPILE_DIR)).await;
+
+        exec(&["exec", "-u1001", "legion-bash", "mkdir", "-m", "777", ARTIFACTS_DIR])
+            .await
+            .unwrap();
+
+        let id = compile(b"compiled").await;
+
+        store("bash", "owned", &id, &Artifacts::default()).await.unwrap();
+
+        assert_eq!(sh(&format!("stat -c %u:%a {}", ARTIFACTS_DIR)).await, "1000:755");
+        assert_eq!(sh(&format!("ls {}", ARTIFACTS_DIR)).await, "owned");
+
+        exec(&["kill", "legion-bash"]).await.unwrap();
+    }
+}
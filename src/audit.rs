@@ -0,0 +1,223 @@
+use std::io;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::error;
+
+use crate::config::Audit;
+
+/// An eval as recorded in the audit log.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub id: String,
+    /// When the eval was received, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The address of the client that sent the eval.
+    pub client: Option<String>,
+    /// The `X-Forwarded-For` header of the request, for clients behind a proxy.
+    pub forwarded_for: Option<String>,
+    pub language: String,
+    pub code: String,
+    /// Size in bytes of the input, if any was given.
+    pub input_size: Option<usize>,
+    pub exit_code: Option<i32>,
+    /// Time in seconds the program ran for.
+    pub duration: Option<f64>,
+    /// `success`, `failure`, `timeout` or `error`.
+    pub verdict: &'static str,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    id: &'a str,
+    timestamp: u64,
+    client: Option<&'a str>,
+    forwarded_for: Option<&'a str>,
+    language: &'a str,
+    code_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'a str>,
+    input_size: Option<usize>,
+    exit_code: Option<i32>,
+    duration: Option<f64>,
+    verdict: &'a str,
+}
+
+/// Appends records to the audit log from a background task, so that evals never wait on it.
+#[derive(Debug)]
+pub struct AuditLog {
+    sender: UnboundedSender<Record>,
+}
+
+impl AuditLog {
+    /// Spawns the task writing the audit log configured by `config`.
+    #[must_use]
+    pub fn new(config: &Audit) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(write(
+            Writer::new(
+                PathBuf::from(&config.path),
+                config.max_size * 1024 * 1024,
+                config.max_files,
+            ),
+            config.include_code,
+            receiver,
+        ));
+
+        Self {
+            sender,
+        }
+    }
+
+    /// Queues `record` to be appended to the audit log.
+    pub fn record(&self, record: Record) {
+        if self.sender.send(record).is_err() {
+            error!("Recording an eval in the audit log failed: the writer stopped.");
+        }
+    }
+}
+
+async fn write(mut writer: Writer, include_code: bool, mut receiver: UnboundedReceiver<Record>) {
+    while let Some(record) = receiver.recv().await {
+        let entry = Entry {
+            id: &record.id,
+            timestamp: record.timestamp,
+            client: record.client.as_deref(),
+            forwarded_for: record.forwarded_for.as_deref(),
+            language: &record.language,
+            code_hash: format!("{:x}", Sha256::digest(&record.code)),
+            code: include_code.then_some(record.code.as_str()),
+            input_size: record.input_size,
+            exit_code: record.exit_code,
+            duration: record.duration,
+            verdict: record.verdict,
+        };
+
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(err) => {
+                error!(id = record.id, error = %err, "Serializing an audit log entry failed.");
+
+                continue;
+            },
+        };
+
+        line.push(b'\n');
+
+        if let Err(err) = writer.write(&line).await {
+            error!(id = record.id, error = %err, "Writing to the audit log failed.");
+        }
+    }
+}
+
+/// Appends lines to a file, rotating it to `<path>.1`, `<path>.2`, ... when it is full.
+struct Writer {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    file: Option<File>,
+    size: u64,
+}
+
+impl Writer {
+    fn new(path: PathBuf, max_size: u64, max_files: u32) -> Self {
+        Self {
+            path,
+            max_size,
+            max_files,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+
+        path.push(format!(".{}", index));
+
+        PathBuf::from(path)
+    }
+
+    async fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open().await?;
+        }
+
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate().await?;
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(line).await?;
+            file.flush().await?;
+        }
+
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    async fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+
+        self.size = file.metadata().await?.len();
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).await?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+
+                if fs::try_exists(&from).await? {
+                    fs::rename(&from, self.rotated_path(index + 1)).await?;
+                }
+            }
+
+            fs::rename(&self.path, self.rotated_path(1)).await?;
+        }
+
+        self.open().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use nanoid::nanoid;
+    use tokio::fs;
+
+    use super::Writer;
+
+    #[tokio::test]
+    async fn audit_log_is_rotated_when_full() {
+        let dir = env::temp_dir().join(format!("legion-audit-{}", nanoid!()));
+
+        fs::create_dir_all(&dir).await.unwrap();
+
+        let mut writer = Writer::new(dir.join("audit.jsonl"), 8, 2);
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            writer.write(line.as_bytes()).await.unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(dir.join("audit.jsonl")).await.unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(dir.join("audit.jsonl.1")).await.unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(dir.join("audit.jsonl.2")).await.unwrap(), "second\n");
+        assert!(!fs::try_exists(dir.join("audit.jsonl.3")).await.unwrap());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
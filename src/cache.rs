@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::time::{Duration, Instant};
+
+use crate::routes::eval::EvalResult;
+
+/// Results of evals, by a hash of everything that determines them, so that identical
+/// submissions are only run once per `ttl`.
+#[derive(Debug)]
+pub struct ResultCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    inserted: Instant,
+    result: EvalResult,
+}
+
+impl ResultCache {
+    #[must_use]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the key of the result of an eval determined by `parts`.
+    #[must_use]
+    pub fn key(parts: &impl Serialize) -> String {
+        let parts = serde_json::to_vec(parts).expect("Serializing the cache key failed");
+
+        format!("{:x}", Sha256::digest(parts))
+    }
+
+    /// Returns the result cached under `key`, unless it expired.
+    pub fn get(&self, key: &str) -> Option<EvalResult> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.get(key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => Some(entry.result.clone()),
+            Some(_) => {
+                entries.remove(key);
+
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// Caches `result` under `key`, evicting expired results, or the oldest one when the cache is
+    /// full of fresh ones.
+    pub fn insert(&self, key: String, result: EvalResult) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.inserted.elapsed() < self.ttl);
+        }
+
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let oldest =
+                entries.iter().min_by_key(|(_, entry)| entry.inserted).map(|(key, _)| key.clone());
+
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(key, Entry {
+            inserted: Instant::now(),
+            result,
+        });
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use nanoid::nanoid;
+use tokio::{fs, task};
+use tracing::error;
+
+use crate::config::{Source, CONFIG_NAME};
+use crate::docker::{
+    build_image,
+    container_exists,
+    image_exists,
+    kill_container,
+    kill_containers,
+    prepare_containers,
+};
+use crate::routes::eval::{run_eval, Eval};
+use crate::supervisor::Supervisor;
+use crate::util::is_docker_available;
+use crate::{Config, Result};
+
+/// Arbitrary code execution server using Docker.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path of the config file, with or without its extension.
+    #[arg(short, long, global = true, default_value = CONFIG_NAME)]
+    pub config: String,
+    /// Port to listen on, overriding `port` of the config.
+    #[arg(short, long, global = true)]
+    pub port: Option<u16>,
+    /// Address to listen on, overriding `host` of the config.
+    #[arg(short, long, global = true)]
+    pub bind: Option<IpAddr>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Starts the server. This is the default.
+    Serve,
+    /// Builds the images of the provided languages, or of every enabled language.
+    Build {
+        /// The languages to build, by default every enabled language.
+        languages: Vec<String>,
+    },
+    /// Builds the images of the enabled languages and starts their containers.
+    Prepare,
+    /// Kills the containers of the enabled languages.
+    Cleanup,
+    /// Runs a file once and prints its output, exiting with its exit code. The standard input
+    /// is passed to the program unless it is a terminal.
+    Run {
+        /// The language to run the file in, which must be enabled.
+        language: String,
+        /// The file with the code to run.
+        file: PathBuf,
+        /// Arguments of the program.
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Validates the config and checks that Docker is available.
+    Check,
+}
+
+impl Cli {
+    /// Returns where the config is loaded from.
+    #[must_use]
+    pub fn source(&self) -> Source {
+        Source {
+            path: self.config.clone(),
+            host: self.bind,
+            port: self.port,
+        }
+    }
+}
+
+/// Builds the images of `languages`, or of every enabled language when empty, reporting every
+/// failed build.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+pub async fn build(config: &Config, languages: &[String]) -> Result<ExitCode> {
+    let languages = if languages.is_empty() { &config.language.enabled } else { languages };
+    let mut failed = false;
+
+    for language in languages {
+        if let Err(err) = build_image(language, true).await {
+            error!(language, error = %err, "Building image failed.");
+
+            failed = true;
+        }
+    }
+
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Builds the images of the enabled languages and starts their containers, reporting every
+/// failed build.
+///
+/// # Errors
+///
+/// - When starting the containers fails.
+pub async fn prepare(config: &Config) -> Result<ExitCode> {
+    let failed =
+        Supervisor::default().build_images(&config.language.enabled, config.update_images).await;
+    let built = config
+        .language
+        .enabled
+        .iter()
+        .filter(|language| !failed.contains(language))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    prepare_containers(&built, &config.language).await?;
+
+    Ok(if failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// Kills the containers of the enabled languages.
+///
+/// # Errors
+///
+/// - When killing the containers fails.
+pub async fn cleanup(config: &Config) -> Result<ExitCode> {
+    kill_containers(&config.language.enabled).await?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `file` once in the container of `language`, building its image and starting its
+/// container when needed. A container started for the run is killed afterwards.
+///
+/// # Errors
+///
+/// - When reading `file` or the standard input fails.
+/// - When running the program fails.
+pub async fn run(
+    config: &Config,
+    language: &str,
+    file: &PathBuf,
+    args: Vec<String>,
+) -> Result<ExitCode> {
+    if !config.language.enabled.iter().any(|enabled| enabled == language) {
+        eprintln!("The language {} is not enabled or does not exist.", language);
+
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let code = fs::read_to_string(file)
+        .await
+        .with_context(|| format!("Reading {} failed", file.display()))?;
+    let input = task::spawn_blocking(|| {
+        let mut stdin = io::stdin();
+
+        if stdin.is_terminal() {
+            return Ok(None);
+        }
+
+        let mut input = String::new();
+
+        stdin.read_to_string(&mut input).map(|_| Some(input))
+    })
+    .await??;
+
+    let was_running = container_exists(language).await?;
+    // The image is built and the container started on demand, as in lazy mode.
+    let config = Arc::new(crate::config::Config {
+        lazy: true,
+        ..(**config).clone()
+    });
+    let payload = Eval {
+        language: language.to_owned(),
+        code,
+        input,
+        args: (!args.is_empty()).then_some(args),
+        env: None,
+        cache: None,
+    };
+    let env = config.language.env.get(language).cloned().unwrap_or_default();
+
+    let result = run_eval(&config, &Supervisor::default(), &payload, &env, &nanoid!()).await;
+
+    if !was_running {
+        kill_container(language).await?;
+    }
+
+    let result = result?;
+
+    io::stdout().write_all(result.stdout.as_bytes())?;
+    io::stderr().write_all(result.stderr.as_bytes())?;
+
+    if result.timed_out {
+        eprintln!("The program timed out after {} seconds.", config.language.timeout);
+
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let code = result.status.code.unwrap_or(libc::EXIT_FAILURE);
+
+    Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)))
+}
+
+/// Checks that Docker is available and reports which images of the enabled languages are built.
+/// The config was validated when it was loaded.
+///
+/// # Errors
+///
+/// - When checking for Docker fails.
+pub async fn check(config: &Config) -> Result<ExitCode> {
+    println!("The config is valid.");
+
+    // A missing Docker CLI fails to run at all.
+    if !matches!(task::spawn_blocking(is_docker_available).await?, Ok(true)) {
+        println!("Docker is not available. Is it installed and running?");
+
+        return Ok(ExitCode::FAILURE);
+    }
+
+    println!("Docker is available.");
+
+    for language in &config.language.enabled {
+        let status = if image_exists(language).await? { "built" } else { "not built" };
+
+        println!("  - {}: {}", language, status);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
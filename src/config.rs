@@ -1,6 +1,36 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+
+use ::config::{Case, Config as ConfigBuilder, Environment, File};
 use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The default path of the config file, which can have any extension `config` supports.
+pub const CONFIG_NAME: &str = "Legion";
+
+/// The port to listen on when `port` is omitted.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// The longest timeout in seconds an evaluation may have.
+const MAX_TIMEOUT: f64 = 60.0 * 60.0;
 
+/// Where the languages that can be enabled are defined, one directory each, which is also the build
+/// context of their images.
+pub const LANGUAGES_DIR: &str = "languages";
+
+/// Keys that were written in snake case before every key was kebab case, by their path, with their
+/// current name. They are still accepted.
+const RENAMED_KEYS: [(&str, &str); 3] = [
+    ("language.max_process_count", "max-process-count"),
+    ("language.max_open_files", "max-open-files"),
+    ("language.max_file_size", "max-file-size"),
+];
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
@@ -8,17 +38,55 @@ pub struct Config {
     pub language: Language,
     #[serde(default = "default_true")]
     pub prepare_containers: bool,
+    #[serde(default = "default_false")]
+    pub lazy: bool,
     #[serde(default = "default_cleanup_interval")]
     pub cleanup_interval: f64,
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval: f64,
     #[serde(default = "default_true")]
     pub update_images: bool,
+    #[serde(default = "default_host")]
+    pub host: IpAddr,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<u16>,
+    /// Serves HTTPS instead of HTTP on `host` and `port`.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    /// Additional addresses to listen on.
+    #[serde(default)]
+    pub listeners: Vec<Listener>,
     #[serde(default = "default_false")]
     pub skip_docker_check: bool,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    #[serde(default)]
+    pub audit: Audit,
+    #[serde(default)]
+    pub store: SubmissionStore,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub artifacts: Artifacts,
+    #[serde(default)]
+    pub admin: Admin,
+}
+
+/// How logs are written to stdout.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Human-readable lines, colored when stdout is a terminal.
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the fields of the event and its span.
+    Json,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Language {
     pub enabled: Vec<String>,
     #[serde(default = "default_memory")]
@@ -31,15 +99,492 @@ pub struct Language {
     pub timeout: f64,
     #[serde(default = "default_retries")]
     pub retries: u8,
-    #[serde(default = "default_max_process_count")]
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    #[serde(default = "default_max_process_count", alias = "max_process_count")]
     pub max_process_count: u32,
-    #[serde(default = "default_max_open_files")]
+    #[serde(default = "default_max_open_files", alias = "max_open_files")]
     pub max_open_files: u32,
-    #[serde(default = "default_max_file_size")]
+    #[serde(default = "default_max_file_size", alias = "max_file_size")]
     pub max_file_size: u32,
+    #[serde(default = "default_max_disk_usage")]
+    pub max_disk_usage: u32,
+    #[serde(default)]
+    pub env: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub max_evals: Option<u64>,
+    #[serde(default)]
+    pub reap: HashMap<String, Reap>,
+}
+
+/// An address to listen on, in addition to `host` and `port`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Listener {
+    /// A socket address like `0.0.0.0:3000`, or the path of a Unix domain socket prefixed with
+    /// `unix:`.
+    pub address: String,
+    /// Serves HTTPS instead of HTTP.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+}
+
+/// The certificate chain and private key to serve HTTPS with, as PEM files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Tls {
+    pub cert: String,
+    pub key: String,
+}
+
+/// Where a listener listens.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Tcp(SocketAddr),
+    Unix(String),
+}
+
+impl Listener {
+    /// Parses the address of the listener.
+    ///
+    /// # Errors
+    ///
+    /// - When the address is neither a socket address nor a Unix domain socket.
+    pub fn address(&self) -> Result<Address, String> {
+        if let Some(path) = self.address.strip_prefix("unix:") {
+            if !cfg!(unix) {
+                return Err(String::from("Unix domain sockets are not supported on this platform"));
+            }
+
+            if path.is_empty() {
+                return Err(String::from("the path of the Unix domain socket is empty"));
+            }
+
+            return Ok(Address::Unix(path.to_owned()));
+        }
+
+        self.address.parse().map(Address::Tcp).map_err(|_| {
+            format!(
+                "`{}` is neither a socket address like `0.0.0.0:3000` nor `unix:` and a path",
+                self.address
+            )
+        })
+    }
+}
+
+/// Export of traces to an OpenTelemetry collector over OTLP.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Telemetry {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// The gRPC endpoint of the collector.
+    #[serde(default = "default_otlp_endpoint")]
+    pub endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// The fraction of traces to sample, from 0 to 1. Traces continued from an incoming
+    /// `traceparent` header follow the sampling decision of the caller.
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+/// Recording of every eval in an append-only JSONL file, for abuse investigation.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Audit {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    #[serde(default = "default_audit_path")]
+    pub path: String,
+    /// Size in megabytes after which the file is rotated.
+    #[serde(default = "default_audit_max_size")]
+    pub max_size: u64,
+    /// The number of rotated files to keep.
+    #[serde(default = "default_audit_max_files")]
+    pub max_files: u32,
+    /// Whether to record the full code of evals instead of only its hash.
+    #[serde(default = "default_false")]
+    pub include_code: bool,
+}
+
+/// Persistence of evals and their results in a `SQLite` database.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SubmissionStore {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    #[serde(default = "default_store_path")]
+    pub path: String,
+    /// Days after which submissions are deleted. Kept forever when omitted.
+    #[serde(default)]
+    pub retention: Option<f64>,
+}
+
+/// Caching of the results of identical evals.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cache {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// Seconds a result is reused for.
+    #[serde(default = "default_cache_ttl")]
+    pub ttl: f64,
+    /// The maximum number of cached results.
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+/// Caching of the compiled artifacts of compiled languages in their containers, so that the same
+/// code is only compiled once.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Artifacts {
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// The maximum number of artifacts kept per container.
+    #[serde(default = "default_artifacts_max_entries")]
+    pub max_entries: u32,
+    /// The maximum size in megabytes of the artifacts kept per container. Counts towards
+    /// `language.max-disk-usage`.
+    #[serde(default = "default_artifacts_max_size")]
+    pub max_size: u32,
+}
+
+/// The admin API, which changes the enabled languages and manages their images and containers
+/// at runtime.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Admin {
+    /// The token admin requests send as `Authorization: Bearer <token>`. The admin API is
+    /// disabled without one.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Which failures of an evaluation are retried.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryPolicy {
+    /// Never retry.
+    Never,
+    /// Only retry when running the program failed, e.g. `docker exec` erroring or the container
+    /// vanishing.
+    #[default]
+    Infrastructure,
+    /// Also retry when the program exits with a non-zero code.
+    NonZeroExit,
+}
+
+/// When an idle container is reaped.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Reap {
+    /// Minutes without evals after which the container is reaped.
+    pub idle: Option<f64>,
+    /// Number of evals after which the container is reaped.
+    pub max_evals: Option<u64>,
+}
+
+/// Something wrong with the config, at the path of the offending field, e.g.
+/// `language.enabled[0]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Problem {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Where the config is loaded from, and the values given on the command line that override it.
+#[derive(Clone, Debug)]
+pub struct Source {
+    /// The path of the config file, with or without its extension.
+    pub path: String,
+    pub host: Option<IpAddr>,
+    pub port: Option<u16>,
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source {
+            path: String::from(CONFIG_NAME),
+            host: None,
+            port: None,
+        }
+    }
+}
+
+/// Loads the config from the file of `source`, which may leave out its extension, overridden by
+/// `LEGION_` environment variables and then the command line, and validates it.
+///
+/// # Errors
+///
+/// - Every problem found, when the file is missing, has unknown keys or invalid values.
+pub fn load(source: &Source) -> Result<Config, Vec<Problem>> {
+    let file = ConfigBuilder::builder()
+        .add_source(File::with_name(&source.path))
+        .build()
+        .and_then(ConfigBuilder::try_deserialize::<Value>)
+        .map_err(|err| vec![Problem::new("", err.to_string())])?;
+
+    let mut problems = Vec::new();
+
+    let known = Config {
+        port: Some(0),
+        ..Config::default()
+    };
+    let known = serde_json::to_value(known).expect("Serializing the default config failed");
+
+    unknown_keys("", &file, &known, &mut problems);
+
+    let config = ConfigBuilder::builder()
+        .add_source(File::with_name(&source.path))
+        .add_source(Environment::with_prefix("LEGION").convert_case(Case::Kebab))
+        .build()
+        .and_then(ConfigBuilder::try_deserialize::<Config>);
+
+    let mut config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            problems.push(Problem::new("", err.to_string()));
+
+            return Err(problems);
+        },
+    };
+
+    config.host = source.host.unwrap_or(config.host);
+    config.port = source.port.or(config.port);
+
+    problems.extend(config.validate());
+
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Reports the keys of `value` that are not in `known`, recursing into tables. Empty tables in
+/// `known` hold arbitrary keys, like `language.env`, and are not checked.
+fn unknown_keys(path: &str, value: &Value, known: &Value, problems: &mut Vec<Problem>) {
+    let (Value::Object(value), Value::Object(known)) = (value, known) else {
+        return;
+    };
+
+    if known.is_empty() {
+        return;
+    }
+
+    let keys = known.keys().cloned().collect::<BTreeSet<_>>();
+
+    for (key, value) in value {
+        let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+
+        let renamed = RENAMED_KEYS.iter().find(|(old, _)| *old == path).map(|(_, new)| *new);
+
+        match known.get(key).or_else(|| renamed.and_then(|key| known.get(key))) {
+            Some(known) => unknown_keys(&path, value, known, problems),
+            None => problems.push(Problem::new(path, unknown("a known key", key, &keys))),
+        }
+    }
+}
+
+/// Returns the names of the languages in the languages directory.
+fn available_languages() -> BTreeSet<String> {
+    fs::read_dir(LANGUAGES_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Describes that `name` is not `what`, suggesting the most similar of `candidates` if one is
+/// close enough to be a typo.
+fn unknown(what: &str, name: &str, candidates: &BTreeSet<String>) -> String {
+    let suggestion = candidates
+        .iter()
+        .map(|candidate| (strsim::damerau_levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= (name.chars().count() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance);
+
+    match suggestion {
+        Some((_, candidate)) => {
+            format!("`{}` is not {}, did you mean `{}`?", name, what, candidate)
+        },
+        None => format!("`{}` is not {}", name, what),
+    }
 }
 
 impl Config {
+    /// Returns when the container of `language` is reaped, falling back to `cleanup-interval` and
+    /// `language.max-evals` for what is not overridden for the language.
+    #[must_use]
+    pub fn reap_policy(&self, language: &str) -> Reap {
+        let overrides = self.language.reap.get(language).copied().unwrap_or_default();
+
+        Reap {
+            idle: Some(overrides.idle.unwrap_or(self.cleanup_interval)),
+            max_evals: overrides.max_evals.or(self.language.max_evals),
+        }
+    }
+
+    /// Checks the values of the config, returning every problem found.
+    #[must_use]
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let language = &self.language;
+        let available = available_languages();
+
+        for (index, name) in language.enabled.iter().enumerate() {
+            let path = format!("language.enabled[{}]", index);
+
+            if !available.contains(name) {
+                problems
+                    .push(Problem::new(path, unknown("an available language", name, &available)));
+            } else if language.enabled[..index].contains(name) {
+                problems.push(Problem::new(path, format!("`{}` is enabled more than once", name)));
+            }
+        }
+
+        let positive = [
+            ("language.memory", f64::from(language.memory)),
+            ("language.cpus", language.cpus),
+            ("language.timeout", language.timeout),
+            ("language.max-process-count", f64::from(language.max_process_count)),
+            ("language.max-open-files", f64::from(language.max_open_files)),
+            ("language.max-file-size", f64::from(language.max_file_size)),
+            ("language.max-disk-usage", f64::from(language.max_disk_usage)),
+            ("health-check-interval", self.health_check_interval),
+            ("artifacts.max-size", f64::from(self.artifacts.max_size)),
+        ];
+
+        for (path, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                problems.push(Problem::new(path, format!("must be positive, got {}", value)));
+            }
+        }
+
+        let non_negative = [
+            (String::from("cleanup-interval"), self.cleanup_interval),
+            (String::from("cache.ttl"), self.cache.ttl),
+        ]
+        .into_iter()
+        .chain(language.reap.iter().filter_map(|(name, reap)| {
+            reap.idle.map(|idle| (format!("language.reap.{}.idle", name), idle))
+        }));
+
+        for (path, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                problems.push(Problem::new(path, format!("must not be negative, got {}", value)));
+            }
+        }
+
+        if language.timeout > MAX_TIMEOUT {
+            problems.push(Problem::new(
+                "language.timeout",
+                format!("must be at most {} seconds, got {}", MAX_TIMEOUT, language.timeout),
+            ));
+        }
+
+        if self.admin.token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+            problems.push(Problem::new("admin.token", "must not be empty"));
+        }
+
+        if self.audit.max_size == 0 {
+            problems.push(Problem::new("audit.max-size", "must be positive, got 0"));
+        }
+
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            problems.push(Problem::new(
+                "telemetry.sample-ratio",
+                format!("must be between 0 and 1, got {}", self.telemetry.sample_ratio),
+            ));
+        }
+
+        if self.store.retention.is_some_and(|retention| !(retention.is_finite() && retention > 0.0))
+        {
+            problems.push(Problem::new("store.retention", "must be positive"));
+        }
+
+        for name in language.env.keys().chain(language.reap.keys()) {
+            if !available.contains(name) {
+                let section = if language.env.contains_key(name) { "env" } else { "reap" };
+
+                problems.push(Problem::new(
+                    format!("language.{}.{}", section, name),
+                    unknown("an available language", name, &available),
+                ));
+            }
+        }
+
+        for (index, listener) in self.listeners.iter().enumerate() {
+            if let Err(message) = listener.address() {
+                problems.push(Problem::new(format!("listeners[{}].address", index), message));
+            }
+        }
+
+        let tls = self.tls.iter().map(|tls| (String::from("tls"), tls)).chain(
+            self.listeners.iter().enumerate().filter_map(|(index, listener)| {
+                listener.tls.as_ref().map(|tls| (format!("listeners[{}].tls", index), tls))
+            }),
+        );
+
+        for (path, tls) in tls {
+            for (key, file) in [("cert", &tls.cert), ("key", &tls.key)] {
+                if !Path::new(file).is_file() {
+                    problems.push(Problem::new(
+                        format!("{}.{}", path, key),
+                        format!("`{}` is not a file", file),
+                    ));
+                }
+            }
+        }
+
+        for (name, vars) in &language.env {
+            for (index, var) in vars.iter().enumerate() {
+                if !var.split_once('=').is_some_and(|(name, _)| !name.is_empty()) {
+                    problems.push(Problem::new(
+                        format!("language.env.{}[{}]", name, index),
+                        format!("`{}` is not of the form `NAME=value`", var),
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Returns every listener, starting with the one on `host` and `port`.
+    #[must_use]
+    pub fn all_listeners(&self) -> Vec<Listener> {
+        let primary = Listener {
+            address: SocketAddr::new(self.host, self.port.unwrap_or(DEFAULT_PORT)).to_string(),
+            tls: self.tls.clone(),
+        };
+
+        [primary].into_iter().chain(self.listeners.iter().cloned()).collect()
+    }
+
     /// Converts the config into a JSON string.
     ///
     /// # Errors
@@ -55,11 +600,23 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             prepare_containers: true,
+            lazy: false,
             cleanup_interval: 10.0,
+            health_check_interval: 30.0,
             update_images: true,
             language: Language::default(),
+            host: default_host(),
             port: None,
+            tls: None,
+            listeners: Vec::new(),
             skip_docker_check: false,
+            log_format: LogFormat::default(),
+            telemetry: Telemetry::default(),
+            audit: Audit::default(),
+            store: SubmissionStore::default(),
+            cache: Cache::default(),
+            artifacts: Artifacts::default(),
+            admin: Admin::default(),
         }
     }
 }
@@ -73,13 +630,75 @@ impl Default for Language {
             runtime: String::from("runc"),
             timeout: 30.0,
             retries: 3,
+            retry_policy: RetryPolicy::default(),
             max_process_count: 128,
             max_open_files: 2048,
             max_file_size: 20_000_000,
+            max_disk_usage: 512,
+            env: HashMap::new(),
+            max_evals: None,
+            reap: HashMap::new(),
+        }
+    }
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry {
+            enabled: false,
+            endpoint: default_otlp_endpoint(),
+            service_name: default_service_name(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Audit {
+            enabled: false,
+            path: default_audit_path(),
+            max_size: 100,
+            max_files: 5,
+            include_code: false,
+        }
+    }
+}
+
+impl Default for SubmissionStore {
+    fn default() -> Self {
+        SubmissionStore {
+            enabled: false,
+            path: default_store_path(),
+            retention: None,
+        }
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            enabled: false,
+            ttl: 300.0,
+            max_entries: 1000,
         }
     }
 }
 
+impl Default for Artifacts {
+    fn default() -> Self {
+        Artifacts {
+            enabled: false,
+            max_entries: 100,
+            max_size: 128,
+        }
+    }
+}
+
+const fn default_host() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
 const fn default_memory() -> u32 {
     512
 }
@@ -104,6 +723,10 @@ const fn default_cleanup_interval() -> f64 {
     10.0
 }
 
+const fn default_health_check_interval() -> f64 {
+    30.0
+}
+
 const fn default_true() -> bool {
     true
 }
@@ -123,3 +746,132 @@ const fn default_max_open_files() -> u32 {
 const fn default_max_file_size() -> u32 {
     20_000_000
 }
+
+const fn default_max_disk_usage() -> u32 {
+    512
+}
+
+fn default_otlp_endpoint() -> String {
+    String::from("http://localhost:4317")
+}
+
+fn default_service_name() -> String {
+    String::from("legion")
+}
+
+const fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+fn default_audit_path() -> String {
+    String::from("audit.jsonl")
+}
+
+const fn default_audit_max_size() -> u64 {
+    100
+}
+
+const fn default_audit_max_files() -> u32 {
+    5
+}
+
+fn default_store_path() -> String {
+    String::from("legion.db")
+}
+
+const fn default_cache_ttl() -> f64 {
+    300.0
+}
+
+const fn default_cache_max_entries() -> usize {
+    1000
+}
+
+const fn default_artifacts_max_entries() -> u32 {
+    100
+}
+
+const fn default_artifacts_max_size() -> u32 {
+    128
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use nanoid::nanoid;
+
+    use super::{load, Config, Language, Source};
+
+    fn config(enabled: &[&str]) -> Config {
+        Config {
+            language: Language {
+                enabled: enabled.iter().map(ToString::to_string).collect(),
+                ..Language::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        assert!(config(&["bash", "python"]).validate().is_empty());
+    }
+
+    #[test]
+    fn typos_in_languages_are_suggested() {
+        let problems = config(&["pyhton"]).validate();
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, "language.enabled[0]");
+        assert!(problems[0].message.contains("did you mean `python`?"));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut config = config(&["bash", "bash"]);
+
+        config.language.cpus = 0.0;
+        config.language.memory = 0;
+        config.language.timeout = 86_400.0;
+        config.language.env.insert(String::from("rubby"), vec![String::from("NOPE")]);
+
+        let paths = config.validate().into_iter().map(|problem| problem.path).collect::<Vec<_>>();
+
+        for path in [
+            "language.enabled[1]",
+            "language.cpus",
+            "language.memory",
+            "language.timeout",
+            "language.env.rubby",
+            "language.env.rubby[0]",
+        ] {
+            assert!(paths.iter().any(|found| found == path), "{} was not reported", path);
+        }
+    }
+
+    #[test]
+    fn snake_case_language_keys_are_accepted() {
+        let path = env::temp_dir().join(format!("legion-config-{}.toml", nanoid!()));
+
+        fs::write(
+            &path,
+            "[language]\nenabled = [\"bash\"]\nmax_process_count = 64\nmax_open_files = 512\n\
+             max_file_size = 1000\n",
+        )
+        .unwrap();
+
+        let config = load(&Source {
+            path: path.display().to_string(),
+            ..Source::default()
+        });
+
+        fs::remove_file(&path).unwrap();
+
+        let language = config.expect("The config is invalid").language;
+
+        assert_eq!(language.max_process_count, 64);
+        assert_eq!(language.max_open_files, 512);
+        assert_eq!(language.max_file_size, 1000);
+    }
+}
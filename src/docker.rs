@@ -1,13 +1,45 @@
 use std::process::{Output, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
 use futures_util::stream::{self, StreamExt};
-use owo_colors::OwoColorize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
-use tracing::{info, warn};
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::config::{Language, LANGUAGES_DIR};
+use crate::metrics::metrics;
+
+/// Why a docker command failed.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Running the Docker CLI failed, e.g. because it is not on your `PATH`.
+    #[error("Running the Docker CLI failed: {0}")]
+    Cli(#[from] std::io::Error),
+    #[error("Building image {image} failed: {output}")]
+    Build { image: String, output: String },
+    #[error("Starting container {container} failed: {output}")]
+    Start { container: String, output: String },
+    #[error("Killing container {container} failed: {output}")]
+    Kill { container: String, output: String },
+    #[error("Writing {path} in container {container} failed: {output}")]
+    Write { container: String, path: String, output: String },
+    /// Docker printed something that could not be parsed.
+    #[error("Unexpected output of docker {command}: {output}")]
+    UnexpectedOutput { command: &'static str, output: String },
+    /// Doing something for several languages failed for some of them, whose errors were logged.
+    #[error("{action} failed for {}", languages.join(", "))]
+    Languages { action: &'static str, languages: Vec<String> },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-use crate::config::Language;
-use crate::util::format_string_vec;
+/// Returns what a failed command printed, preferring its standard error.
+fn failure_output(output: &Output) -> String {
+    String::from_utf8_lossy(if output.stderr.is_empty() { &output.stdout } else { &output.stderr })
+        .trim()
+        .to_owned()
+}
 
 /// Executes a docker command.
 ///
@@ -27,14 +59,70 @@ pub async fn exec(args: &[&str]) -> Result<Output> {
     Ok(output)
 }
 
-/// Starts a docker container with the provided `language` and an optional `runtime`.
+/// Executes a docker command, streaming `stdin` into it.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When writing to the standard input of the command fails.
+#[tracing::instrument(skip(args, stdin))]
+pub async fn exec_with_stdin(args: &[&str], stdin: &[u8]) -> Result<Output> {
+    let mut child = Command::new("docker")
+        .args(args)
+        .stderr(Stdio::piped())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut child_stdin) = child.stdin.take() {
+        child_stdin.write_all(stdin).await?;
+    }
+
+    Ok(child.wait_with_output().await?)
+}
+
+/// Writes `contents` to `path` inside the container of the provided `language`.
+///
+/// The contents are streamed through the standard input of `docker exec`, so they never
+/// show up on the command line of either the host or the container.
 ///
 /// # Errors
 ///
 /// - When the Docker CLI is not on your `PATH`.
+/// - When writing the file fails.
+#[tracing::instrument(skip(contents))]
+pub async fn write_file(language: &str, user: &str, path: &str, contents: &[u8]) -> Result<()> {
+    let output = exec_with_stdin(
+        &[
+            "exec",
+            "-i",
+            &format!("-u{}", user),
+            &format!("legion-{}", language),
+            "/bin/sh",
+            "-c",
+            "cat > \"$0\"",
+            path,
+        ],
+        contents,
+    )
+    .await?;
+
+    if !output.status.success() {
+        return Err(Error::Write {
+            container: format!("legion-{}", language),
+            path: path.to_owned(),
+            output: failure_output(&output),
+        });
+    }
+
+    Ok(())
+}
+
+/// Starts a docker container with the provided `language` and an optional `runtime`.
 ///
-/// # Panics
+/// # Errors
 ///
+/// - When the Docker CLI is not on your `PATH`.
 /// - When starting the container fails.
 #[tracing::instrument(skip(config))]
 pub async fn start_container(language: &str, config: &Language) -> Result<()> {
@@ -63,142 +151,203 @@ pub async fn start_container(language: &str, config: &Language) -> Result<()> {
     ])
     .await?;
 
-    assert!(
-        output.status.success(),
-        "Starting container {} failed: {}",
-        image.bold().underline(),
-        String::from_utf8_lossy(if output.stderr.is_empty() {
-            &output.stdout
-        } else {
-            &output.stderr
-        })
-        .bright_red()
-    );
+    if !output.status.success() {
+        return Err(Error::Start {
+            container: image,
+            output: failure_output(&output),
+        });
+    }
+
+    metrics().container_starts.with_label_values(&[language]).inc();
 
     Ok(())
 }
 
-/// Builds multiple docker images.
+/// Restarts the container of the provided `language`, starting it if it is not running.
 ///
 /// # Errors
 ///
 /// - When the Docker CLI is not on your `PATH`.
+/// - When killing or starting the container fails.
+#[tracing::instrument(skip(config))]
+pub async fn restart_container(language: &str, config: &Language) -> Result<()> {
+    if container_exists(language).await? {
+        kill_container(language).await?;
+    }
+
+    start_container(language, config).await
+}
+
+/// Builds the docker image of the provided `language`, unless it is present and `update_images`
+/// is `false`.
 ///
-/// # Panics
+/// # Errors
 ///
+/// - When the Docker CLI is not on your `PATH`.
 /// - When building the image fails.
-#[tracing::instrument]
-pub async fn build_images(languages: &[String], update_images: bool) -> Result<()> {
-    async fn build_image(language: String, update_images: bool) {
-        let output = &exec(&["images", "-q", &format!("legion-{}", language)])
-            .await
-            .expect("Failed checking images");
-
-        let is_image_present = String::from_utf8_lossy(&output.stdout);
-
-        if update_images || is_image_present.trim().is_empty() {
-            info!("Building image {}...", format!("legion-{}", language).bold().underline());
-
-            let output = exec(&[
-                "build",
-                "-t",
-                &format!("legion-{}", language),
-                &format!("languages/{}", language),
-            ])
-            .await
-            .expect("Failed building image");
-
-            assert!(
-                output.status.success(),
-                "Building image {} failed: {}",
-                format!("legion-{}", language).bold().underline(),
-                String::from_utf8_lossy(if output.stderr.is_empty() {
-                    &output.stdout
-                } else {
-                    &output.stderr
-                })
-                .bright_red()
-            );
-
-            info!("Finished building image {}.", format!("legion-{}", language).bold().underline());
+pub async fn build_image(language: &str, update_images: bool) -> Result<()> {
+    build_image_with_log(language, update_images, |_| {}).await
+}
+
+/// Builds the docker image of the provided `language` like [`build_image`], passing every line
+/// of the build output to `log` as it is printed.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When building the image fails.
+#[tracing::instrument(skip(log))]
+pub async fn build_image_with_log(
+    language: &str,
+    update_images: bool,
+    mut log: impl FnMut(&str) + Send,
+) -> Result<()> {
+    let image = format!("legion-{}", language);
+
+    if !update_images && image_exists(language).await? {
+        return Ok(());
+    }
+
+    info!("Building image...");
+
+    let started = Instant::now();
+    let mut child = Command::new("docker")
+        .args(["build", "-t", &image, &format!("{}/{}", LANGUAGES_DIR, language)])
+        .stderr(Stdio::piped())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(Error::Cli(std::io::Error::other("The output of docker build is not piped")));
+    };
+    // Builds may print anything, so their output is split into lines as bytes.
+    let mut stdout = BufReader::new(stdout).split(b'\n');
+    let mut stderr = BufReader::new(stderr).split(b'\n');
+    let (mut stdout_done, mut stderr_done) = (false, false);
+    let mut output = Vec::new();
+
+    // BuildKit prints its progress to stderr, the legacy builder to stdout.
+    loop {
+        let (line, done) = tokio::select! {
+            line = stdout.next_segment(), if !stdout_done => (line?, &mut stdout_done),
+            line = stderr.next_segment(), if !stderr_done => (line?, &mut stderr_done),
+            else => break,
+        };
+
+        match line {
+            Some(line) => {
+                let line = String::from_utf8_lossy(&line).into_owned();
+
+                log(&line);
+                output.push(line);
+            },
+            None => *done = true,
         }
     }
 
-    info!("{}", "Building images...".blue());
+    if !child.wait().await?.success() {
+        return Err(Error::Build {
+            image,
+            output: output.join("\n"),
+        });
+    }
 
-    let languages = languages.to_vec();
+    let duration = started.elapsed().as_secs_f64();
 
-    stream::iter(
-        languages.into_iter().map(|language| tokio::spawn(build_image(language, update_images))),
-    )
-    .buffer_unordered(10)
-    .collect::<Vec<_>>()
-    .await;
+    metrics().image_build_duration.with_label_values(&[language]).observe(duration);
 
-    info!("{}", "Finished building images.".green());
+    info!(duration, "Finished building image.");
 
     Ok(())
 }
 
-/// Starts the docker containers for use.
+/// Starts the docker containers for use, restarting those that already exist. Every language
+/// is tried, even when starting the container of another fails.
 ///
 /// # Errors
 ///
-/// - When the Docker CLI is not on your `PATH`.
-///
-/// # Panics
-///
-/// - When starting the container fails.
+/// - When starting any of the containers fails.
 #[tracing::instrument(skip(config))]
 pub async fn prepare_containers(languages: &[String], config: &Language) -> Result<()> {
-    info!("{}", "Preparing containers...".blue());
+    info!("Preparing containers...");
+
+    let mut failed = Vec::new();
 
     for language in languages {
-        let container_exists = container_exists(language).await?;
-
-        if container_exists {
-            warn!(
-                "{}",
-                format!("Container legion-{} already exists. Restarting.", language).bright_red()
-            );
-
-            exec(&["kill", &format!("legion-{}", language)]).await?;
-            start_container(language, config).await?;
-        } else {
-            start_container(language, config).await?;
+        if let Err(err) = restart_container(language, config).await {
+            error!(language, error = %err, "Starting container failed.");
+
+            failed.push(language.clone());
         }
     }
 
-    info!("{}", "Finished preparing containers.".green());
+    info!("Finished preparing containers.");
+
+    if !failed.is_empty() {
+        return Err(Error::Languages {
+            action: "Starting containers",
+            languages: failed,
+        });
+    }
+
+    Ok(())
+}
+
+/// Kills the container of a language, which is not an error when it is not running.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+/// - When the container is still running after killing it failed.
+#[tracing::instrument]
+pub async fn kill_container(language: &str) -> Result<()> {
+    let container = format!("legion-{}", language);
+    let output = exec(&["kill", &container]).await?;
+
+    if !output.status.success() && container_exists(language).await? {
+        return Err(Error::Kill {
+            container,
+            output: failure_output(&output),
+        });
+    }
 
     Ok(())
 }
 
-/// Kill running containers.
+/// Kill running containers. Every container is tried, even when killing another fails.
 ///
 /// # Errors
 ///
-/// - When killing the container fails.
+/// - When killing any of the containers fails.
 #[tracing::instrument]
 pub async fn kill_containers(languages: &[String]) -> Result<()> {
-    let formatted_languages = format_string_vec(languages);
+    info!("Killing containers...");
 
-    info!("Killing containers {}...", formatted_languages.underline());
+    let failed = stream::iter(languages.to_vec())
+        .map(|language| async move {
+            let result = kill_container(&language).await;
 
-    let languages = languages.to_vec();
+            if let Err(err) = &result {
+                error!(language, error = %err, "Killing container failed.");
+            }
 
-    stream::iter(languages.into_iter().map(|language| {
-        tokio::spawn(async move {
-            exec(&["kill", &format!("legion-{}", language)])
-                .await
-                .expect("Failed killing container")
+            result.is_err().then_some(language)
         })
-    }))
-    .buffer_unordered(10)
-    .collect::<Vec<_>>()
-    .await;
-
-    info!("Killed containers {}.", formatted_languages.underline());
+        .buffer_unordered(10)
+        .filter_map(|failed| async move { failed })
+        .collect::<Vec<_>>()
+        .await;
+
+    info!("Killed containers.");
+
+    if !failed.is_empty() {
+        return Err(Error::Languages {
+            action: "Killing containers",
+            languages: failed,
+        });
+    }
 
     Ok(())
 }
@@ -212,3 +361,187 @@ pub async fn kill_containers(languages: &[String]) -> Result<()> {
 pub async fn container_exists(language: &str) -> Result<bool> {
     Ok(exec(&["top", &format!("legion-{}", language)]).await?.status.success())
 }
+
+/// The state of a container, as reported by `docker inspect`.
+#[derive(Clone, Debug)]
+pub struct ContainerState {
+    /// The status of the container, e.g. `running` or `paused`.
+    pub status: String,
+    /// When the container was started.
+    pub started_at: Option<SystemTime>,
+}
+
+/// The resource usage of a container, as reported by `docker stats`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContainerUsage {
+    /// The CPU usage in percent of one core.
+    pub cpu: f64,
+    /// The memory usage in bytes.
+    pub memory: u64,
+    /// The memory limit in bytes.
+    pub memory_limit: u64,
+    /// The number of processes and threads.
+    pub pids: u64,
+}
+
+/// Returns the state of the container of a language, or `None` if it does not exist.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn container_state(language: &str) -> Result<Option<ContainerState>> {
+    let output = exec(&[
+        "inspect",
+        "--format",
+        "{{.State.Status}} {{.State.StartedAt}}",
+        &format!("legion-{}", language),
+    ])
+    .await?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let state = String::from_utf8_lossy(&output.stdout);
+    let (status, started_at) = state.trim().split_once(' ').unwrap_or((state.trim(), ""));
+
+    Ok(Some(ContainerState {
+        status: status.to_owned(),
+        started_at: parse_timestamp(started_at),
+    }))
+}
+
+/// Returns the resource usage of the container of a language, or `None` if it is not running.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn container_usage(language: &str) -> Result<Option<ContainerUsage>> {
+    let output = exec(&[
+        "stats",
+        "--no-stream",
+        "--format",
+        "{{.CPUPerc}};{{.MemUsage}};{{.PIDs}}",
+        &format!("legion-{}", language),
+    ])
+    .await?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let stats = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stats.trim().split(';');
+    let (Some(cpu), Some(memory), Some(pids)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(Error::UnexpectedOutput {
+            command: "stats",
+            output: stats.trim().to_owned(),
+        });
+    };
+    let (memory, memory_limit) = memory.split_once('/').unwrap_or((memory, ""));
+
+    Ok(Some(ContainerUsage {
+        cpu: cpu.trim().trim_end_matches('%').parse().unwrap_or_default(),
+        memory: parse_size(memory).unwrap_or_default(),
+        memory_limit: parse_size(memory_limit).unwrap_or_default(),
+        pids: pids.trim().parse().unwrap_or_default(),
+    }))
+}
+
+/// Parses a size as printed by the Docker CLI, e.g. `1.5MiB` or `12kB`, into bytes.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|char: char| char.is_ascii_alphabetic()).unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+    let value = value.trim().parse::<f64>().ok()?;
+    let multiplier = match unit {
+        "B" | "" => 1_u64,
+        "kB" | "KB" => 1_000,
+        "KiB" => 1 << 10,
+        "MB" => 1_000_000,
+        "MiB" => 1 << 20,
+        "GB" => 1_000_000_000,
+        "GiB" => 1 << 30,
+        "TB" => 1_000_000_000_000,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss, clippy::cast_sign_loss)]
+    Some((value * multiplier as f64).round() as u64)
+}
+
+/// Parses a UTC timestamp as printed by the Docker CLI, e.g. `2024-05-01T12:34:56.789Z`.
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.trim().strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let nanos = format!("{:0<9}", fraction).get(..9)?.parse::<u32>().ok()?;
+
+    // Days since the Unix epoch of the civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146_097 + day_of_era - 719_468).ok()?;
+
+    // Containers that never started have the zero time of Go, which is before the epoch.
+    Some(UNIX_EPOCH + Duration::new(days * 86_400 + hours * 3_600 + minutes * 60 + seconds, nanos))
+}
+
+/// Returns the ID of the image of a language, or `None` if it does not exist.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn image_id(language: &str) -> Result<Option<String>> {
+    let output =
+        exec(&["image", "inspect", "--format", "{{.Id}}", &format!("legion-{}", language)]).await?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+    Ok((output.status.success() && !id.is_empty()).then_some(id))
+}
+
+/// Check if the image of a language exists.
+///
+/// # Errors
+///
+/// - When the Docker CLI is not on your `PATH`.
+#[tracing::instrument]
+pub async fn image_exists(language: &str) -> Result<bool> {
+    let output = exec(&["images", "-q", &format!("legion-{}", language)]).await?;
+
+    Ok(!String::from_utf8_lossy(&output.stdout).trim().is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{parse_size, parse_timestamp};
+
+    #[test]
+    fn docker_sizes_and_timestamps_are_parsed() {
+        assert_eq!(parse_size("1.5MiB"), Some(1_572_864));
+        assert_eq!(parse_size(" 512MiB"), Some(536_870_912));
+        assert_eq!(parse_size("12kB "), Some(12_000));
+        assert_eq!(parse_size("0B"), Some(0));
+        assert_eq!(parse_size("12 parsecs"), None);
+
+        assert_eq!(
+            parse_timestamp("2024-05-01T12:34:56.5Z"),
+            Some(UNIX_EPOCH + Duration::from_millis(1_714_566_896_500))
+        );
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+        assert_eq!(parse_timestamp("0001-01-01T00:00:00Z"), None);
+    }
+}
@@ -1,11 +1,63 @@
+use containers::{Container, ContainerStatus, Usage};
 use eval::{Eval, EvalResult, EvalStatus};
-use utoipa::OpenApi;
+use health::{Health, LanguageHealth};
+use languages::{ImageBuild, LanguageReadiness, ReadinessStatus};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
-use crate::routes::{cleanup, containers, eval, languages};
+use crate::routes::{admin, cleanup, containers, eval, health, languages, metrics, submissions};
+use crate::store::{Submission, SubmissionStatus};
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(cleanup::cleanup, containers::containers, eval::eval, languages::languages),
-    components(schemas(Eval, EvalResult, EvalStatus))
+    paths(
+        admin::enable,
+        admin::disable,
+        admin::rebuild,
+        cleanup::cleanup,
+        containers::containers,
+        containers::container,
+        containers::restart,
+        containers::kill,
+        eval::eval,
+        health::healthz,
+        health::readyz,
+        languages::languages,
+        languages::readiness,
+        languages::build,
+        languages::build_log,
+        metrics::metrics,
+        submissions::submissions
+    ),
+    components(schemas(
+        Container,
+        ContainerStatus,
+        Eval,
+        EvalResult,
+        EvalStatus,
+        Health,
+        ImageBuild,
+        LanguageHealth,
+        LanguageReadiness,
+        ReadinessStatus,
+        Submission,
+        SubmissionStatus,
+        Usage
+    )),
+    modifiers(&AdminToken)
 )]
 pub struct Docs;
+
+/// The bearer token of the admin API.
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
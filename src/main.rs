@@ -10,22 +10,41 @@
 
 #[cfg(not(unix))]
 use std::future;
+use std::io::{self, IsTerminal};
+use std::process::{self, ExitCode};
 use std::sync::Arc;
 use std::time::Duration;
 
-use ::config::{Config as ConfigBuilder, Environment, File};
-use axum::extract::MatchedPath;
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use audit::AuditLog;
+use axum::extract::{FromRef, MatchedPath};
 use axum::http::Request;
 use axum::response::Redirect;
-use axum::routing::{get, post};
-use axum::Router;
+use axum::routing::{delete, get, post};
+use axum::{middleware, Router};
+use cache::ResultCache;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{LogFormat, Source};
 use docs::Docs;
-use routes::{cleanup, containers, eval, languages};
-use tokio::net::TcpListener;
-use tokio::{signal, time};
+use routes::{
+    admin,
+    cleanup,
+    containers,
+    eval,
+    health,
+    languages,
+    metrics as metrics_route,
+    submissions,
+};
+use store::Store;
+use supervisor::Supervisor;
+use tokio::signal;
 use tower_http::trace::{DefaultOnRequest, DefaultOnResponse, TraceLayer};
 use tower_http::LatencyUnit;
-use tracing::{info_span, warn, Level};
+use tracing::{error, info_span, warn, Level};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
@@ -33,20 +52,132 @@ use util::{check_if_docker_exists, print_intro};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+pub mod artifacts;
+pub mod audit;
+pub mod cache;
+pub mod cli;
 mod config;
 pub mod docker;
 mod docs;
 pub mod error;
+pub mod metrics;
+pub mod reload;
 pub mod routes;
+pub mod server;
+pub mod store;
+pub mod supervisor;
+pub mod telemetry;
 mod util;
 
 pub type Result<T> = anyhow::Result<T, error::AppError>;
 pub type Config = Arc<config::Config>;
+/// The current config, which is swapped out when it is reloaded.
+pub type SharedConfig = Arc<ArcSwap<config::Config>>;
+
+#[derive(Clone, Debug)]
+pub struct AppState {
+    pub config: SharedConfig,
+    pub supervisor: Arc<Supervisor>,
+    pub audit: Option<Arc<AuditLog>>,
+    pub store: Option<Arc<Store>>,
+    pub cache: Option<Arc<ResultCache>>,
+}
+
+impl AppState {
+    /// Creates the state of the app, opening the submission store if it is enabled.
+    ///
+    /// # Errors
+    ///
+    /// - When opening the submission store fails.
+    pub fn new(config: Config) -> Result<Self> {
+        let store = if config.store.enabled {
+            let store = Store::open(&config.store.path).with_context(|| {
+                format!("Opening the submission store at {} failed", config.store.path)
+            })?;
+
+            Some(Arc::new(store))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            audit: config.audit.enabled.then(|| Arc::new(AuditLog::new(&config.audit))),
+            store,
+            cache: config.cache.enabled.then(|| {
+                Arc::new(ResultCache::new(
+                    Duration::from_secs_f64(config.cache.ttl),
+                    config.cache.max_entries,
+                ))
+            }),
+            config: Arc::new(ArcSwap::new(config)),
+            supervisor: Arc::new(Supervisor::default()),
+        })
+    }
+}
+
+impl FromRef<AppState> for Config {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.load_full()
+    }
+}
+
+impl FromRef<AppState> for SharedConfig {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.config)
+    }
+}
+
+impl FromRef<AppState> for Arc<Supervisor> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.supervisor)
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<AuditLog>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.audit.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<Store>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<ResultCache>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.cache.clone()
+    }
+}
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let source = cli.source();
+    let config = config::load(&source).unwrap_or_else(|problems| {
+        eprintln!("The config is invalid:");
+
+        for problem in problems {
+            eprintln!("  - {}", problem);
+        }
+
+        process::exit(libc::EXIT_FAILURE);
+    });
+
+    let provider = telemetry::init(&config.telemetry).expect("Setting up trace export failed");
+
+    let ansi = config.log_format == LogFormat::Pretty && io::stdout().is_terminal();
+    let fmt_layer = match config.log_format {
+        LogFormat::Pretty => fmt::layer().with_ansi(ansi).boxed(),
+        LogFormat::Json => fmt::layer().json().flatten_event(true).with_span_list(false).boxed(),
+    };
+
     tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(fmt_layer)
+        .with(provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(telemetry::tracer(provider))
+        }))
         .with(
             EnvFilter::builder()
                 .with_env_var("LEGION_LOG")
@@ -55,84 +186,148 @@ async fn main() -> Result<()> {
         )
         .init();
 
-    let config: config::Config = ConfigBuilder::builder()
-        .add_source(File::with_name("Legion"))
-        .add_source(Environment::with_prefix("LEGION"))
-        .build()
-        .expect("Couldn't find config file")
-        .try_deserialize()
-        .expect("Deserializing config failed");
+    let config = Arc::new(config);
+    let exit_code = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            // The banner is for humans, and needs a terminal to be centered in.
+            if ansi {
+                print_intro(&config)?;
+            }
 
-    print_intro(&Arc::new(config.clone()))?;
+            serve(source, config).await?
+        },
+        Command::Build {
+            languages,
+        } => cli::build(&config, &languages).await?,
+        Command::Prepare => cli::prepare(&config).await?,
+        Command::Cleanup => cli::cleanup(&config).await?,
+        Command::Run {
+            language,
+            file,
+            args,
+        } => cli::run(&config, &language, &file, args).await?,
+        Command::Check => cli::check(&config).await?,
+    };
 
-    if !config.skip_docker_check {
-        check_if_docker_exists().expect("Checking for docker failed");
+    if let Some(provider) = provider {
+        provider.shutdown()?;
     }
 
-    docker::build_images(&config.language.enabled, config.update_images).await?;
+    Ok(exit_code)
+}
 
-    if config.prepare_containers {
-        docker::prepare_containers(&config.language.enabled, &config.language).await?;
+/// Prepares the enabled languages and serves the API on every listener, until a shutdown signal.
+async fn serve(source: Source, config: Config) -> Result<ExitCode> {
+    if !config.skip_docker_check {
+        check_if_docker_exists()?;
     }
 
-    let port = config.port.unwrap_or(3000);
+    let state = AppState::new(config)?;
+    let config = state.config.load_full();
 
-    let config = Arc::new(config);
-    let config_2 = Arc::clone(&config);
+    if config.language.enabled.is_empty() {
+        warn!("No languages are enabled. Every eval is rejected until one is.");
+    }
 
-    tokio::spawn(async move {
-        let mut interval =
-            time::interval(Duration::from_secs_f64(config_2.cleanup_interval * 60.0));
+    // In lazy mode, images are built and containers started on their first eval instead.
+    if !config.lazy {
+        let failed =
+            state.supervisor.build_images(&config.language.enabled, config.update_images).await;
+        let built = config
+            .language
+            .enabled
+            .iter()
+            .filter(|language| !failed.contains(language))
+            .cloned()
+            .collect::<Vec<_>>();
 
-        // ticks immediately
-        interval.tick().await;
+        if !failed.is_empty() {
+            error!(
+                languages = ?failed,
+                "Building images failed. These languages are unavailable until they are rebuilt."
+            );
+        }
 
-        loop {
-            interval.tick().await;
-            docker::kill_containers(&config_2.language.enabled)
-                .await
-                .expect("Failed killing containers");
+        // Containers that fail to start are started again by the health checks.
+        if config.prepare_containers {
+            if let Err(err) = docker::prepare_containers(&built, &config.language).await {
+                error!(error = %err, "Preparing containers failed.");
+            }
         }
-    });
+    }
+
+    tokio::spawn(supervisor::run(Arc::clone(&state.config), Arc::clone(&state.supervisor)));
+    tokio::spawn(reload::run(source, Arc::clone(&state.config), Arc::clone(&state.supervisor)));
+
+    if let (Some(store), Some(retention)) = (&state.store, config.store.retention) {
+        tokio::spawn(store::run(Arc::clone(store), retention));
+    }
 
-    let app = app(config.clone());
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    let shared = Arc::clone(&state.config);
+    let app = app(state);
 
-    axum::serve(listener, app).with_graceful_shutdown(shutdown_signal(config)).await?;
+    server::serve(app, &config.all_listeners(), shutdown_signal(shared)).await?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-pub fn app(config: Config) -> Router {
+pub fn app(state: AppState) -> Router {
+    let admin = Router::new()
+        .route("/languages/:language/enable", post(admin::enable))
+        .route("/languages/:language/disable", post(admin::disable))
+        .route("/languages/:language/rebuild", post(admin::rebuild))
+        .route("/languages/:language/restart", post(containers::restart))
+        .route_layer(middleware::from_fn_with_state(state.clone(), admin::authorize));
+    // Killing and restarting containers and listing submissions are admin requests, on paths
+    // outside of the admin API.
+    let admin_paths = Router::new()
+        .route("/api/cleanup", post(cleanup::cleanup))
+        .route("/api/containers/:language", delete(containers::kill))
+        .route("/api/containers/:language/restart", post(containers::restart))
+        .route("/api/submissions", get(submissions::submissions))
+        .route_layer(middleware::from_fn_with_state(state.clone(), admin::authorize));
+
     Router::new()
         .merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", Docs::openapi()))
         .route("/", get(|| async { Redirect::temporary("/docs") }))
-        .route("/api/cleanup", post(cleanup::cleanup))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics_route::metrics))
         .route("/api/containers", get(containers::containers))
+        .route("/api/containers/:language", get(containers::container))
+        .merge(admin_paths)
         .route("/api/eval", post(eval::eval))
         .route("/api/languages", get(languages::languages))
+        .route("/api/languages/readiness", get(languages::readiness))
+        .route("/api/languages/:language/build", get(languages::build))
+        .route("/api/languages/:language/build/log", get(languages::build_log))
+        .nest("/api/admin", admin)
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &Request<_>| {
                     let matched_path =
                         request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
 
-                    info_span!(
+                    let span = info_span!(
                         "http_request",
                         method = ?request.method(),
                         matched_path
-                    )
+                    );
+
+                    span.set_parent(telemetry::remote_context(request.headers()));
+
+                    span
                 })
                 .on_request(DefaultOnRequest::new().level(Level::INFO))
                 .on_response(
                     DefaultOnResponse::new().level(Level::INFO).latency_unit(LatencyUnit::Micros),
                 ),
         )
-        .with_state(config)
+        .with_state(state)
 }
 
 #[allow(clippy::ignored_unit_patterns)]
-async fn shutdown_signal(config: Config) {
+async fn shutdown_signal(config: SharedConfig) {
     let ctrl_c = async {
         signal::ctrl_c().await.unwrap();
     };
@@ -152,5 +347,7 @@ async fn shutdown_signal(config: Config) {
 
     warn!("Shutdown signal received. Killing containers.");
 
-    docker::kill_containers(&config.language.enabled).await.expect("Failed killing containers");
+    if let Err(err) = docker::kill_containers(&config.load().language.enabled).await {
+        error!(error = %err, "Killing containers failed.");
+    }
 }
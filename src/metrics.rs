@@ -0,0 +1,133 @@
+use std::sync::OnceLock;
+
+use prometheus::{
+    HistogramOpts,
+    HistogramVec,
+    IntCounterVec,
+    IntGaugeVec,
+    Opts,
+    Registry,
+    TextEncoder,
+};
+
+/// Buckets in seconds for the duration of evals and the time they wait before running.
+const EVAL_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Buckets in seconds for the duration of image builds.
+const BUILD_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0];
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The Prometheus metrics of Legion.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// Finished evals, by `language` and `outcome` (`success`, `failure`, `timeout` or `error`).
+    pub evals: IntCounterVec,
+    /// Time the program of an eval ran for, by `language`.
+    pub eval_duration: HistogramVec,
+    /// Time an eval waited for its container before its program ran, by `language`.
+    pub queue_wait: HistogramVec,
+    /// Evals killed for exceeding the timeout, by `language`.
+    pub timeouts: IntCounterVec,
+    /// Containers started, including restarts, by `language`.
+    pub container_starts: IntCounterVec,
+    /// Runs of a program that failed because of docker, by `language`: the CLI could not be run, or
+    /// `docker exec` failed itself.
+    pub exec_failures: IntCounterVec,
+    /// Time building an image took, by `language`.
+    pub image_build_duration: HistogramVec,
+    /// Evals currently running, by `language`.
+    pub in_flight: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some(String::from("legion")), None)
+            .expect("Creating the metrics registry failed");
+
+        let evals = IntCounterVec::new(Opts::new("evals_total", "Finished evals."), &[
+            "language", "outcome",
+        ])
+        .unwrap();
+        let eval_duration = HistogramVec::new(
+            HistogramOpts::new("eval_duration_seconds", "Time the program of an eval ran for.")
+                .buckets(EVAL_BUCKETS.to_vec()),
+            &["language"],
+        )
+        .unwrap();
+        let queue_wait = HistogramVec::new(
+            HistogramOpts::new(
+                "eval_queue_wait_seconds",
+                "Time an eval waited for its container before its program ran.",
+            )
+            .buckets(EVAL_BUCKETS.to_vec()),
+            &["language"],
+        )
+        .unwrap();
+        let timeouts = IntCounterVec::new(
+            Opts::new("eval_timeouts_total", "Evals killed for exceeding the timeout."),
+            &["language"],
+        )
+        .unwrap();
+        let container_starts = IntCounterVec::new(
+            Opts::new("container_starts_total", "Containers started, including restarts."),
+            &["language"],
+        )
+        .unwrap();
+        let exec_failures = IntCounterVec::new(
+            Opts::new(
+                "docker_exec_failures_total",
+                "Runs of a program that failed because of docker.",
+            ),
+            &["language"],
+        )
+        .unwrap();
+        let image_build_duration = HistogramVec::new(
+            HistogramOpts::new("image_build_duration_seconds", "Time building an image took.")
+                .buckets(BUILD_BUCKETS.to_vec()),
+            &["language"],
+        )
+        .unwrap();
+        let in_flight =
+            IntGaugeVec::new(Opts::new("evals_in_flight", "Evals currently running."), &[
+                "language",
+            ])
+            .unwrap();
+
+        registry.register(Box::new(evals.clone())).unwrap();
+        registry.register(Box::new(eval_duration.clone())).unwrap();
+        registry.register(Box::new(queue_wait.clone())).unwrap();
+        registry.register(Box::new(timeouts.clone())).unwrap();
+        registry.register(Box::new(container_starts.clone())).unwrap();
+        registry.register(Box::new(exec_failures.clone())).unwrap();
+        registry.register(Box::new(image_build_duration.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+
+        Self {
+            registry,
+            evals,
+            eval_duration,
+            queue_wait,
+            timeouts,
+            container_starts,
+            exec_failures,
+            image_build_duration,
+            in_flight,
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    ///
+    /// # Errors
+    ///
+    /// - When encoding the metrics fails.
+    pub fn render(&self) -> prometheus::Result<String> {
+        TextEncoder::new().encode_to_string(&self.registry.gather())
+    }
+}
+
+/// Returns the metrics of Legion.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::config::{self, Config, Problem, Source};
+use crate::docker::start_container;
+use crate::supervisor::Supervisor;
+use crate::SharedConfig;
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The extensions a config file can have.
+const EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml", "ini", "ron", "json5"];
+
+/// Top-level keys of the config that are only read at startup.
+const RESTART_KEYS: &[&str] = &[
+    "host",
+    "port",
+    "tls",
+    "listeners",
+    "skip-docker-check",
+    "log-format",
+    "telemetry",
+    "audit",
+    "store",
+    "cache",
+];
+
+/// What differs between two configs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Languages that were enabled.
+    pub added: Vec<String>,
+    /// Languages that were disabled.
+    pub removed: Vec<String>,
+    /// Whether the limits containers are started with changed, so that they need recycling.
+    pub container_limits: bool,
+    /// Keys that changed, but only take effect after a restart.
+    pub restart_required: Vec<&'static str>,
+}
+
+impl Changes {
+    /// Compares the `old` config with the `new` one.
+    #[must_use]
+    pub fn between(old: &Config, new: &Config) -> Self {
+        let (old_language, new_language) = (&old.language, &new.language);
+
+        let old_values = serde_json::to_value(old).unwrap_or_default();
+        let new_values = serde_json::to_value(new).unwrap_or_default();
+
+        Self {
+            added: new_language
+                .enabled
+                .iter()
+                .filter(|language| !old_language.enabled.contains(language))
+                .cloned()
+                .collect(),
+            removed: old_language
+                .enabled
+                .iter()
+                .filter(|language| !new_language.enabled.contains(language))
+                .cloned()
+                .collect(),
+            container_limits: old_language.memory != new_language.memory
+                || old_language.cpus.to_bits() != new_language.cpus.to_bits()
+                || old_language.runtime != new_language.runtime,
+            restart_required: RESTART_KEYS
+                .iter()
+                .copied()
+                .filter(|key| old_values.get(key) != new_values.get(key))
+                .collect(),
+        }
+    }
+}
+
+/// Returns when the config files at `path`, with or without an extension, were last modified,
+/// so that any change to them, including one being created or removed, changes the result.
+fn modified(path: &str) -> Vec<Option<SystemTime>> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| PathBuf::from(format!("{}.{}", path, extension)))
+        .chain([PathBuf::from(path)])
+        .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+/// Loads the config from `source` again and applies it. When the new config is invalid, the
+/// current one is kept.
+pub async fn reload(source: &Source, config: &SharedConfig, supervisor: &Supervisor) {
+    let _updating = supervisor.lock_config().await;
+
+    let new = match config::load(source) {
+        Ok(new) => new,
+        Err(problems) => {
+            for problem in problems {
+                error!(%problem, "Invalid config.");
+            }
+
+            error!("Reloading the config failed. Keeping the current config.");
+
+            return;
+        },
+    };
+
+    apply(config, supervisor, new).await;
+}
+
+/// Changes a copy of the current config with `change` and applies it, unless the result is
+/// invalid. The change is lost when the config file is reloaded.
+///
+/// # Errors
+///
+/// - Every problem of the changed config.
+pub async fn update(
+    config: &SharedConfig,
+    supervisor: &Supervisor,
+    change: impl FnOnce(&mut Config),
+) -> Result<Arc<Config>, Vec<Problem>> {
+    let _updating = supervisor.lock_config().await;
+
+    let mut new = Config::clone(&config.load());
+
+    change(&mut new);
+
+    let problems = new.validate();
+
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    Ok(apply(config, supervisor, new).await)
+}
+
+/// Swaps in the `new` config: newly enabled languages are prepared, disabled ones are stopped and
+/// containers started with other limits are recycled. Every following eval uses the new config.
+async fn apply(config: &SharedConfig, supervisor: &Supervisor, new: Config) -> Arc<Config> {
+    let new = Arc::new(new);
+    let old = config.load_full();
+    let changes = Changes::between(&old, &new);
+
+    for key in &changes.restart_required {
+        warn!(key, "Changing the config key requires a restart to take effect.");
+    }
+
+    if new.language.enabled.is_empty() {
+        warn!("No languages are enabled. Every eval is rejected until one is.");
+    }
+
+    // Languages are prepared before the swap, so that no eval runs in them before they are.
+    for language in &changes.added {
+        supervisor.enable(language);
+        prepare(language, &new, supervisor).await;
+    }
+
+    config.store(Arc::clone(&new));
+
+    for language in &changes.removed {
+        if let Err(err) = supervisor.retire(language).await {
+            error!(language, error = %err, "Stopping container of disabled language failed.");
+        }
+    }
+
+    if changes.container_limits {
+        for language in &new.language.enabled {
+            supervisor.mark_dirty(language);
+        }
+    }
+
+    info!(
+        added = ?changes.added,
+        removed = ?changes.removed,
+        recycled = changes.container_limits,
+        "Applied the config."
+    );
+
+    new
+}
+
+/// Builds the image of a newly enabled `language` and starts its container, unless that is left
+/// to its first eval in lazy mode.
+async fn prepare(language: &str, config: &Config, supervisor: &Supervisor) {
+    if config.lazy {
+        return;
+    }
+
+    if let Err(err) = supervisor.build(language, config.update_images).await {
+        error!(language, error = %err, "Building image of enabled language failed.");
+
+        return;
+    }
+
+    if config.prepare_containers {
+        if let Err(err) = start_container(language, &config.language).await {
+            error!(language, error = %err, "Starting container of enabled language failed.");
+        }
+    }
+}
+
+/// Reloads the config from `source` whenever its file changes or the process receives `SIGHUP`.
+pub async fn run(source: Source, config: SharedConfig, supervisor: Arc<Supervisor>) {
+    #[cfg(unix)]
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(err) => {
+            error!(error = %err, "Listening for SIGHUP failed.");
+
+            None
+        },
+    };
+
+    let mut interval = time::interval(POLL_INTERVAL);
+    let mut last_modified = modified(&source.path);
+
+    loop {
+        #[cfg(unix)]
+        let hangup = async {
+            match &mut hangup {
+                Some(hangup) => hangup.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = hangup => {
+                last_modified = modified(&source.path);
+
+                info!("Received SIGHUP. Reloading the config.");
+            },
+            _ = interval.tick() => {
+                let modified = modified(&source.path);
+
+                if modified == last_modified {
+                    continue;
+                }
+
+                last_modified = modified;
+
+                info!("Config file changed. Reloading the config.");
+            },
+        }
+
+        reload(&source, &config, &supervisor).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Changes;
+    use crate::config::{Config, Language};
+
+    fn config(enabled: &[&str]) -> Config {
+        Config {
+            language: Language {
+                enabled: enabled.iter().map(ToString::to_string).collect(),
+                ..Language::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn changes_between_configs_are_found() {
+        let old = config(&["bash", "python"]);
+        let mut new = config(&["python", "ruby"]);
+
+        new.language.timeout = 5.0;
+
+        assert_eq!(Changes::between(&old, &new), Changes {
+            added: vec![String::from("ruby")],
+            removed: vec![String::from("bash")],
+            ..Changes::default()
+        });
+
+        new.language.memory *= 2;
+        new.port = Some(4000);
+
+        let changes = Changes::between(&old, &new);
+
+        assert!(changes.container_limits);
+        assert_eq!(changes.restart_required, ["port"]);
+    }
+}
@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::config::Problem;
+use crate::reload::update;
+use crate::routes::not_enabled;
+use crate::supervisor::{Readiness, Supervisor};
+use crate::{Config, SharedConfig};
+
+/// Rejects requests without the admin token, and every request while the admin API is disabled.
+pub async fn authorize(State(config): State<Config>, request: Request, next: Next) -> Response {
+    let Some(token) = &config.admin.token else {
+        return (StatusCode::NOT_FOUND, "The admin API is not enabled.").into_response();
+    };
+
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    // Comparing digests takes the same time wherever the tokens differ.
+    if !given.is_some_and(|given| Sha256::digest(given) == Sha256::digest(token)) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "The admin token is missing or wrong.",
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Responds with the enabled languages after a change, or why the change was invalid.
+fn enabled_languages(result: std::result::Result<Config, Vec<Problem>>) -> Response {
+    match result {
+        Ok(config) => Json(config.language.enabled.clone()).into_response(),
+        Err(problems) => (
+            StatusCode::BAD_REQUEST,
+            problems.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/languages/{language}/enable",
+    params(("language" = String, Path, description = "The language to enable.", example = "python")),
+    responses(
+        (status = 200, body = Vec<String>, description = "The enabled languages."),
+        (status = 400, description = "The language does not exist."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API is not enabled."),
+        (status = 500, description = "Server error.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn enable(
+    State(config): State<SharedConfig>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Response {
+    let result = update(&config, &supervisor, |config| {
+        if !config.language.enabled.contains(&language) {
+            config.language.enabled.push(language);
+        }
+    })
+    .await;
+
+    enabled_languages(result)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/languages/{language}/disable",
+    params(("language" = String, Path, description = "The language to disable.", example = "python")),
+    responses(
+        (status = 200, body = Vec<String>, description = "The enabled languages."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API is not enabled."),
+        (status = 500, description = "Server error.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn disable(
+    State(config): State<SharedConfig>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Response {
+    let result = update(&config, &supervisor, |config| {
+        config.language.enabled.retain(|enabled| *enabled != language);
+    })
+    .await;
+
+    enabled_languages(result)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/languages/{language}/rebuild",
+    params(("language" = String, Path, description = "The language to rebuild.", example = "python")),
+    responses(
+        (status = 202, description = "The image is being rebuilt, which `/api/languages/{language}/build` reports on. Its running container is restarted on the new image once the build succeeds."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API or the language is not enabled."),
+        (status = 409, description = "The image is already being built."),
+        (status = 500, description = "Server error.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn rebuild(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Response {
+    if !config.language.enabled.contains(&language) {
+        return not_enabled(&language);
+    }
+
+    if supervisor.readiness(&language) == Readiness::Building {
+        return (
+            StatusCode::CONFLICT,
+            format!("The image of {} is already being built.", language),
+        )
+            .into_response();
+    }
+
+    tokio::spawn(async move {
+        // The outcome is reported by the build status.
+        if let Err(err) = supervisor.rebuild(&language, &config).await {
+            error!(language, error = %err, "Rebuilding image failed.");
+        }
+    });
+
+    StatusCode::ACCEPTED.into_response()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{header, Method, Request, StatusCode};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use crate::config::{Admin, Config, Language};
+    use crate::{app, AppState};
+
+    pub(crate) fn admin_app(token: Option<&str>) -> Router {
+        app(AppState::new(Arc::new(Config {
+            lazy: true,
+            language: Language {
+                enabled: vec![String::from("bash"), String::from("python")],
+                ..Language::default()
+            },
+            admin: Admin {
+                token: token.map(ToOwned::to_owned),
+            },
+            ..Config::default()
+        }))
+        .unwrap())
+    }
+
+    pub(crate) fn request(method: Method, uri: &str, token: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder().method(method).uri(uri);
+
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        request.body(Body::empty()).unwrap()
+    }
+
+    pub(crate) async fn body(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn admin_requests_are_authorized() {
+        let uri = "/api/admin/languages/ruby/enable";
+
+        let disabled = admin_app(None);
+        let app = admin_app(Some("secret"));
+
+        assert_eq!(
+            body(&disabled, request(Method::POST, uri, Some("secret"))).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(body(&app, request(Method::POST, uri, None)).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body(&app, request(Method::POST, uri, Some("wrong"))).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(body(&app, request(Method::POST, uri, Some("secret"))).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn languages_are_enabled_and_disabled() {
+        let app = admin_app(Some("secret"));
+        let token = Some("secret");
+
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/ruby/enable", token)).await,
+            (StatusCode::OK, String::from(r#"["bash","python","ruby"]"#))
+        );
+
+        let (status, message) =
+            body(&app, request(Method::POST, "/api/admin/languages/pyhton/enable", token)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.contains("did you mean `python`?"));
+
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/python/disable", token)).await,
+            (StatusCode::OK, String::from(r#"["bash","ruby"]"#))
+        );
+        assert_eq!(
+            body(&app, request(Method::GET, "/api/languages", None)).await,
+            (StatusCode::OK, String::from(r#"["bash","ruby"]"#))
+        );
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/ruby/disable", token)).await,
+            (StatusCode::OK, String::from(r#"["bash"]"#))
+        );
+        // Disabling the last language leaves the server running without any.
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/bash/disable", token)).await,
+            (StatusCode::OK, String::from("[]"))
+        );
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/python/restart", token)).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/admin/languages/python/rebuild", token)).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -10,8 +10,11 @@ use crate::{Config, Result};
     path = "/api/cleanup",
     responses(
         (status = 204),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API is not enabled."),
         (status = 500, description = "Server error.")
-    )
+    ),
+    security(("admin_token" = []))
 )]
 pub async fn cleanup(State(config): State<Config>) -> Result<Response> {
     kill_containers(&config.language.enabled).await?;
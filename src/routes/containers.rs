@@ -1,24 +1,246 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::docker::{container_state, container_usage, exec};
+use crate::routes::not_enabled;
+use crate::supervisor::Supervisor;
+use crate::util::unix_seconds;
+use crate::{Config, Result};
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Container {
+    #[schema(example = "legion-javascript")]
+    name: String,
+    #[schema(example = "javascript")]
+    language: String,
+    /// The number of evals currently running in the container.
+    #[schema(example = 0)]
+    in_flight: usize,
+    /// The number of evals run since the container was last recycled.
+    #[schema(example = 12)]
+    evals: u64,
+    /// When the last eval finished, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_000)]
+    last_used: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ContainerStatus {
+    #[schema(example = "legion-javascript")]
+    name: String,
+    #[schema(example = "javascript")]
+    language: String,
+    /// The status reported by Docker, or `stopped` if the container does not exist.
+    #[schema(example = "running")]
+    status: String,
+    /// When the container was started, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_000)]
+    started_at: Option<u64>,
+    /// How long the container has been running, in seconds.
+    #[schema(example = 3600)]
+    uptime: Option<u64>,
+    /// The resource usage of the container, if it is running.
+    usage: Option<Usage>,
+    /// The number of evals currently running in the container.
+    #[schema(example = 0)]
+    in_flight: usize,
+    /// The number of evals run since the container was last recycled.
+    #[schema(example = 12)]
+    evals: u64,
+    /// When the last eval finished, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_000)]
+    last_used: Option<u64>,
+}
 
-use crate::docker::exec;
-use crate::Result;
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Usage {
+    /// The CPU usage in percent of one core.
+    #[schema(example = 0.5)]
+    cpu: f64,
+    /// The memory usage in bytes.
+    #[schema(example = 1_572_864)]
+    memory: u64,
+    /// The memory limit in bytes.
+    #[schema(example = 536_870_912)]
+    memory_limit: u64,
+    /// The number of processes and threads.
+    #[schema(example = 1)]
+    pids: u64,
+}
 
 #[utoipa::path(
     get,
     path = "/api/containers",
     responses(
-        (status = 200, body = Vec<String>),
+        (status = 200, body = Vec<Container>),
         (status = 500, description = "Server error.")
     )
 )]
-pub async fn containers() -> Result<Response> {
+pub async fn containers(State(supervisor): State<Arc<Supervisor>>) -> Result<Response> {
     let output = exec(&["ps", "--filter", "name=legion-", "--format", "{{.Names}}"]).await?;
 
     let list = String::from_utf8_lossy(&output.stdout)
         .lines()
-        .map(|ln| ln.trim().to_owned())
+        .map(|ln| {
+            let name = ln.trim().to_owned();
+            let language = name.trim_start_matches("legion-").to_owned();
+            let stats = supervisor.stats(&language);
+
+            Container {
+                in_flight: stats.in_flight,
+                evals: stats.evals,
+                last_used: stats.last_used.and_then(unix_seconds),
+                name,
+                language,
+            }
+        })
         .collect::<Vec<_>>();
 
     Ok(Json(list).into_response())
 }
+
+#[utoipa::path(
+    get,
+    path = "/api/containers/{language}",
+    params(("language" = String, Path, description = "The language of the container.", example = "python")),
+    responses(
+        (status = 200, body = ContainerStatus),
+        (status = 404, description = "The language is not enabled."),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn container(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Result<Response> {
+    if !config.language.enabled.contains(&language) {
+        return Ok(not_enabled(&language));
+    }
+
+    let state = container_state(&language).await?;
+    let usage = container_usage(&language).await?;
+    let supervised = supervisor.stats(&language);
+    let started_at = state.as_ref().and_then(|state| state.started_at);
+
+    Ok(Json(ContainerStatus {
+        name: format!("legion-{}", language),
+        status: state.map_or_else(|| String::from("stopped"), |state| state.status),
+        started_at: started_at.and_then(unix_seconds),
+        uptime: started_at
+            .and_then(|started_at| started_at.elapsed().ok())
+            .map(|uptime| uptime.as_secs()),
+        usage: usage.map(|usage| Usage {
+            cpu: usage.cpu,
+            memory: usage.memory,
+            memory_limit: usage.memory_limit,
+            pids: usage.pids,
+        }),
+        in_flight: supervised.in_flight,
+        evals: supervised.evals,
+        last_used: supervised.last_used.and_then(unix_seconds),
+        language,
+    })
+    .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/containers/{language}/restart",
+    params(("language" = String, Path, description = "The language of the container.", example = "python")),
+    responses(
+        (status = 204, description = "The container was restarted once its evals finished. Also served at `/api/admin/languages/{language}/restart`."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API or the language is not enabled."),
+        (status = 500, description = "Restarting the container failed.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn restart(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Result<Response> {
+    if !config.language.enabled.contains(&language) {
+        return Ok(not_enabled(&language));
+    }
+
+    supervisor.restart(&language, &config).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/containers/{language}",
+    params(("language" = String, Path, description = "The language of the container.", example = "python")),
+    responses(
+        (status = 204, description = "The container was killed once its evals finished, and is started again on the next eval."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API or the language is not enabled."),
+        (status = 500, description = "Killing the container failed.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn kill(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Result<Response> {
+    if !config.language.enabled.contains(&language) {
+        return Ok(not_enabled(&language));
+    }
+
+    supervisor.kill(&language).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{Method, StatusCode};
+
+    use crate::routes::admin::test::{admin_app, body, request};
+
+    #[tokio::test]
+    async fn containers_are_managed_with_the_admin_token() {
+        let disabled = admin_app(None);
+        let app = admin_app(Some("secret"));
+        let token = Some("secret");
+
+        for (method, uri) in [
+            (Method::DELETE, "/api/containers/bash"),
+            (Method::POST, "/api/containers/bash/restart"),
+            (Method::POST, "/api/admin/languages/bash/restart"),
+            (Method::POST, "/api/cleanup"),
+        ] {
+            assert_eq!(
+                body(&disabled, request(method.clone(), uri, token)).await.0,
+                StatusCode::NOT_FOUND
+            );
+            assert_eq!(
+                body(&app, request(method.clone(), uri, None)).await.0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        assert_eq!(
+            body(&app, request(Method::DELETE, "/api/containers/ruby", token)).await,
+            (StatusCode::NOT_FOUND, String::from("The language ruby is not enabled."))
+        );
+        assert_eq!(
+            body(&app, request(Method::POST, "/api/containers/ruby/restart", token)).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            body(&app, request(Method::GET, "/api/containers/ruby", None)).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -1,47 +1,128 @@
-use std::process::Output;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::process::{Output, Stdio};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
 
-use anyhow::anyhow;
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use nanoid::nanoid;
-use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use tokio::process::Command;
-use tokio::time::{sleep, Duration};
+use tokio::task;
+use tokio::time::{sleep, timeout, Duration, Instant};
 use tracing::{error, info, warn};
 use utoipa::ToSchema;
 
-use crate::docker::{container_exists, exec, start_container};
+use crate::artifacts::{self, Restore, COMPILE_SCRIPT, COMPILE_USER};
+use crate::audit::{AuditLog, Record};
+use crate::cache::ResultCache;
+use crate::config::RetryPolicy;
+use crate::docker::{container_exists, exec, image_id, write_file};
+use crate::metrics::metrics;
+use crate::store::{Store, Submission, SubmissionStatus};
+use crate::supervisor::{Lease, Supervisor};
+use crate::util::unix_seconds;
 use crate::{Config, Result};
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct Eval {
     #[schema(example = "javascript")]
-    language: String,
+    pub language: String,
     #[schema(example = "console.log('Hello, World!');")]
-    code: String,
-    input: Option<String>,
-    args: Option<Vec<String>>,
+    pub code: String,
+    /// Bytes passed to the standard input of the program exactly as given. When omitted, the
+    /// standard input is `/dev/null`.
+    pub input: Option<String>,
+    pub args: Option<Vec<String>>,
+    /// Environment variables of the program, on top of the defaults of the language.
+    pub env: Option<BTreeMap<String, String>>,
+    /// Whether the result of an identical earlier eval may be returned, when caching is enabled.
+    /// With `false`, the program always runs and its result replaces the cached one.
+    #[schema(example = true)]
+    pub cache: Option<bool>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct EvalResult {
     #[schema(example = "Hello, World!")]
-    stdout: String,
-    stderr: String,
-    status: EvalStatus,
+    pub stdout: String,
+    pub stderr: String,
+    pub status: EvalStatus,
+    /// The number of times the program was run, which is 0 when it did not compile in time or at
+    /// all.
+    #[schema(example = 1)]
+    pub attempts: u16,
+    /// Whether the program was killed for exceeding the timeout, in which case `stdout` and
+    /// `stderr` hold what it printed until then.
+    #[schema(example = false)]
+    pub timed_out: bool,
+    /// Time in seconds the program ran for.
+    #[schema(example = 0.25)]
+    pub elapsed: f64,
+    /// Whether this is the result of an identical earlier eval.
+    #[serde(default)]
+    #[schema(example = false)]
+    pub cached: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct EvalStatus {
     #[schema(example = true)]
-    success: bool,
+    pub success: bool,
     #[schema(example = 0)]
-    code: Option<i32>,
+    pub code: Option<i32>,
 }
 
+impl EvalResult {
+    /// Returns `success`, `failure` or `timeout`.
+    fn outcome(&self) -> &'static str {
+        match (self.timed_out, self.status.success) {
+            (true, _) => "timeout",
+            (false, true) => "success",
+            (false, false) => "failure",
+        }
+    }
+
+    /// Returns whether the result only depends on the submission, so that identical evals may be
+    /// answered with it. Timed-out runs depend on the load of the host, and runs that failed
+    /// because of docker, or even after retrying, on its infrastructure.
+    fn is_cacheable(&self) -> bool {
+        !self.timed_out
+            && (self.status.success || self.attempts <= 1)
+            && !self.status.code.is_some_and(|code| EXEC_FAILURE_CODES.contains(&code))
+    }
+}
+
+/// The compilation of an eval ahead of its run.
+struct Compilation {
+    output: Option<Output>,
+    elapsed: Duration,
+    timed_out: bool,
+}
+
+/// The script of a language that runs the code of an eval.
+const RUN_SCRIPT: &str = "/var/run/run.sh";
+
+/// The user programs run as.
+const EVAL_USER: &str = "1001:1001";
+
+/// How long to wait for the output of a timed-out eval after killing it.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// The exit codes of `docker exec` itself, when the daemon failed and when the command could not
+/// be invoked.
+const EXEC_FAILURE_CODES: &[i32] = &[125, 126];
+
+/// Environment variables that evals are not allowed to set.
+const DENIED_ENV: &[&str] = &["PATH", "HOME", "USER", "SHELL", "PWD", "IFS", "ENV", "BASH_ENV"];
+
+/// Prefixes of environment variables that evals are not allowed to set.
+const DENIED_ENV_PREFIXES: &[&str] = &["LD_"];
+
 #[utoipa::path(
     post,
     path = "/api/eval",
@@ -49,11 +130,24 @@ pub struct EvalStatus {
     responses(
         (status = 200, body = EvalResult),
         (status = 500, description = "Server error."),
+        (status = 400, description = "An environment variable is invalid or not allowed."),
         (status = 404, description = "Language is not enabled or does not exist."),
-        (status = 408, description = "Execution timeout.")
+        (status = 408, body = EvalResult, description = "Execution timeout.")
     )
 )]
-pub async fn eval(State(config): State<Config>, Json(payload): Json<Eval>) -> Result<Response> {
+#[allow(clippy::too_many_arguments)]
+pub async fn eval(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    State(audit): State<Option<Arc<AuditLog>>>,
+    State(store): State<Option<Arc<Store>>>,
+    State(cache): State<Option<Arc<ResultCache>>>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(payload): Json<Eval>,
+) -> Result<Response> {
+    let received = unix_seconds(SystemTime::now()).unwrap_or_default();
+
     if !config.language.enabled.contains(&payload.language) {
         return Ok((
             StatusCode::NOT_FOUND,
@@ -62,27 +156,370 @@ pub async fn eval(State(config): State<Config>, Json(payload): Json<Eval>) -> Re
             .into_response());
     }
 
+    let mut env = config.language.env.get(&payload.language).cloned().unwrap_or_default();
+
+    for (name, value) in payload.env.iter().flatten() {
+        if let Err(message) = validate_env(name) {
+            return Ok((StatusCode::BAD_REQUEST, message).into_response());
+        }
+
+        env.push(format!("{}={}", name, value));
+    }
+
     let id = nanoid!();
-    let container_present = container_exists(&payload.language).await?;
-
-    if !container_present {
-        if config.prepare_containers {
-            warn!(
-                "[{}] Container legion-{} is not present. Starting a new container.",
-                id.yellow(),
-                payload.language
-            );
+    // The eval runs uncached if its image cannot be inspected.
+    let cache_key = match &cache {
+        Some(_) => cache_key(&config, &payload, &env).await.ok().flatten(),
+        None => None,
+    };
+    let cached = match (&cache, &cache_key) {
+        (Some(cache), Some(key)) if payload.cache != Some(false) => cache.get(key),
+        _ => None,
+    };
+
+    let result = match cached {
+        Some(result) => Ok(EvalResult {
+            cached: true,
+            ..result
+        }),
+        None => run_eval(&config, &supervisor, &payload, &env, &id).await,
+    };
+    let outcome = result.as_ref().map_or("error", EvalResult::outcome);
 
-            start_container(&payload.language, &config.language).await?;
-        } else {
-            error!("[{}] Container legion-{} is not present.", id.yellow(), payload.language);
+    metrics().evals.with_label_values(&[&payload.language, outcome]).inc();
 
-            return Err(
-                anyhow!(format!("Container legion-{} does not exist.", payload.language)).into()
-            );
+    if let Ok(result) = &result {
+        if !result.cached {
+            metrics().eval_duration.with_label_values(&[&payload.language]).observe(result.elapsed);
         }
+
+        if let (Some(cache), Some(key)) = (&cache, cache_key) {
+            if !result.cached && result.is_cacheable() {
+                cache.insert(key, result.clone());
+            }
+        }
+    }
+
+    if let Some(audit) = audit {
+        audit.record(Record {
+            id: id.clone(),
+            timestamp: received,
+            client: client.map(|ConnectInfo(address)| address.ip().to_string()),
+            forwarded_for: headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+            language: payload.language.clone(),
+            code: payload.code.clone(),
+            input_size: payload.input.as_ref().map(String::len),
+            exit_code: result.as_ref().ok().and_then(|result| result.status.code),
+            duration: result.as_ref().ok().map(|result| result.elapsed),
+            verdict: outcome,
+        });
+    }
+
+    if let Some(store) = store {
+        let submission = Submission {
+            id,
+            created_at: received,
+            language: payload.language.clone(),
+            code: payload.code.clone(),
+            input: payload.input.clone(),
+            args: payload.args.clone(),
+            stdout: result.as_ref().map(|result| result.stdout.clone()).unwrap_or_default(),
+            stderr: result.as_ref().map(|result| result.stderr.clone()).unwrap_or_default(),
+            status: SubmissionStatus::from_outcome(outcome),
+            exit_code: result.as_ref().ok().and_then(|result| result.status.code),
+            attempts: result.as_ref().map_or(0, |result| result.attempts),
+            duration: result.as_ref().ok().map(|result| result.elapsed),
+        };
+
+        // Saved in the background, so that a slow disk does not hold up the response.
+        task::spawn_blocking(move || {
+            if let Err(err) = store.insert(&submission) {
+                error!(id = submission.id, error = %err, "Saving the submission failed.");
+            }
+        });
+    }
+
+    let result = result?;
+
+    if result.timed_out {
+        return Ok((StatusCode::REQUEST_TIMEOUT, Json(result)).into_response());
+    }
+
+    Ok(Json(result).into_response())
+}
+
+/// Runs `payload` in the container of its language with the environment variables `env`, as the
+/// eval with the provided `id`.
+///
+/// # Errors
+///
+/// - When the container is not running and may not be started.
+/// - When running the program fails.
+#[tracing::instrument(skip(config, supervisor, payload, env), fields(language = %payload.language))]
+pub async fn run_eval(
+    config: &Config,
+    supervisor: &Supervisor,
+    payload: &Eval,
+    env: &[String],
+    id: &str,
+) -> Result<EvalResult> {
+    let queued = Instant::now();
+    let lease = supervisor.lease(&payload.language).await;
+
+    supervisor.ensure_container(&payload.language, config).await?;
+    prepare_eval(payload, id).await?;
+
+    metrics()
+        .queue_wait
+        .with_label_values(&[&payload.language])
+        .observe(queued.elapsed().as_secs_f64());
+
+    info!("Running eval.");
+
+    let compilation = compile_eval(config, &lease, payload, env, id).await?;
+    let compile_time =
+        compilation.as_ref().map_or(Duration::ZERO, |compilation| compilation.elapsed);
+    // The compilation and the run share the timeout.
+    let run_timeout = Duration::from_secs_f64(config.language.timeout).saturating_sub(compile_time);
+    let dir = format!("/tmp/eval/{}", id);
+    let mut attempts: u16 = 0;
+
+    #[allow(clippy::ignored_unit_patterns)]
+    let (output, timed_out, elapsed) = loop {
+        match &compilation {
+            Some(compilation) if compilation.timed_out => break (None, true, Duration::ZERO),
+            // The compiler already reported why the code cannot run.
+            Some(Compilation {
+                output: Some(output), ..
+            }) if !output.status.success() => break (None, false, Duration::ZERO),
+            _ => {},
+        }
+
+        attempts += 1;
+
+        let started = Instant::now();
+        let pgid = OnceLock::new();
+        let run = _eval(
+            &payload.language,
+            RUN_SCRIPT,
+            payload.args.as_deref(),
+            env,
+            &dir,
+            EVAL_USER,
+            config.clone(),
+            Some(&pgid),
+        );
+
+        tokio::pin!(run);
+
+        let output = tokio::select! {
+            _ = sleep(run_timeout) => None,
+            output = &mut run => Some(output),
+        };
+
+        let Some(output) = output else {
+            warn!(timeout = config.language.timeout, "Eval timed out.");
+
+            metrics().timeouts.with_label_values(&[&payload.language]).inc();
+
+            // Without an id the program never started, so there is nothing to kill.
+            if let Some(&pgid) = pgid.get() {
+                kill_eval(&payload.language, pgid).await?;
+            }
+
+            // Processes that escaped the process group may linger, so the container is recycled
+            // once it is idle.
+            lease.mark_dirty();
+
+            // Killing the process group closes its pipes, so the run finishes with whatever was
+            // printed so far, unless a stray process escaped the group and holds them open.
+            let output = timeout(KILL_GRACE_PERIOD, run).await.ok().transpose()?;
+
+            break (output, true, started.elapsed());
+        };
+
+        let should_retry = match &output {
+            Err(_) => config.language.retry_policy != RetryPolicy::Never,
+            Ok(output) if output.status.success() => false,
+            Ok(_) => match config.language.retry_policy {
+                RetryPolicy::Never => false,
+                RetryPolicy::NonZeroExit => true,
+                // A non-zero exit is the program's own verdict, unless the container vanished
+                // under it.
+                RetryPolicy::Infrastructure => !container_exists(&payload.language).await?,
+            },
+        };
+
+        if is_exec_failure(&output) {
+            metrics().exec_failures.with_label_values(&[&payload.language]).inc();
+        }
+
+        if !should_retry || attempts > u16::from(config.language.retries) {
+            break (Some(output?), false, started.elapsed());
+        }
+
+        warn!(attempt = attempts, retries = config.language.retries, "Eval failed. Retrying.");
+
+        if !container_exists(&payload.language).await? {
+            supervisor.ensure_container(&payload.language, config).await?;
+            prepare_eval(payload, id).await?;
+        }
+    };
+
+    cleanup_eval(&payload.language, id).await?;
+
+    // What the compiler printed comes before what the program printed, as when the run script
+    // compiles the code itself.
+    let compiled = compilation.as_ref().and_then(|compilation| compilation.output.as_ref());
+    let outputs = [compiled, output.as_ref()];
+    // A program that did not compile has the status of its compiler.
+    let status = output.as_ref().or(compiled);
+
+    let response = EvalResult {
+        stdout: outputs
+            .iter()
+            .flatten()
+            .map(|output| String::from_utf8_lossy(&output.stdout))
+            .collect(),
+        stderr: outputs
+            .iter()
+            .flatten()
+            .map(|output| String::from_utf8_lossy(&output.stderr))
+            .collect(),
+        status: EvalStatus {
+            success: !timed_out && status.is_some_and(|output| output.status.success()),
+            code: status.and_then(|output| output.status.code()),
+        },
+        attempts,
+        timed_out,
+        elapsed: (compile_time + elapsed).as_secs_f64(),
+        cached: false,
+    };
+
+    info!(
+        outcome = response.outcome(),
+        code = response.status.code,
+        attempts,
+        duration = response.elapsed,
+        "Finished eval."
+    );
+
+    Ok(response)
+}
+
+/// Compiles the code of the eval ahead of its run when compiled artifacts are cached, restoring the
+/// artifact from the cache instead if it is there.
+///
+/// Returns `None` when the run script is left to compile the code, or the artifact was restored.
+async fn compile_eval(
+    config: &Config,
+    lease: &Lease,
+    payload: &Eval,
+    env: &[String],
+    id: &str,
+) -> Result<Option<Compilation>> {
+    if !config.artifacts.enabled {
+        return Ok(None);
+    }
+
+    let Some(key) = artifacts::key(&payload.language, &payload.code, env).await? else {
+        return Ok(None);
+    };
+
+    if artifacts::restore(&payload.language, &key, id).await? != Restore::Miss {
+        return Ok(None);
     }
 
+    // Compilations are serialized, so that every process of the compile user that is left once
+    // the compiler finishes belongs to this eval, and is killed before the artifact is cached.
+    let _compiling = lease.lock_compilation().await;
+    let started = Instant::now();
+
+    artifacts::prepare(&payload.language, id, &payload.code).await?;
+
+    let dir = artifacts::compile_dir(id);
+    let compile = _eval(
+        &payload.language,
+        COMPILE_SCRIPT,
+        None,
+        env,
+        &dir,
+        COMPILE_USER,
+        config.clone(),
+        None,
+    );
+    let output = timeout(Duration::from_secs_f64(config.language.timeout), compile).await;
+
+    artifacts::kill_compilers(&payload.language).await?;
+
+    let compilation = if let Ok(output) = output {
+        let output = output?;
+
+        // Failed compilations are not cached, so that they are retried and report their errors.
+        if output.status.success() {
+            if let Err(err) = artifacts::store(&payload.language, &key, id, &config.artifacts).await
+            {
+                warn!(error = %err, "Caching the compiled artifact failed.");
+            }
+
+            artifacts::copy_compiled(&payload.language, id).await?;
+        }
+
+        Compilation {
+            output: Some(output),
+            elapsed: started.elapsed(),
+            timed_out: false,
+        }
+    } else {
+        warn!(timeout = config.language.timeout, "Compiling the eval timed out.");
+
+        metrics().timeouts.with_label_values(&[&payload.language]).inc();
+
+        Compilation {
+            output: None,
+            elapsed: started.elapsed(),
+            timed_out: true,
+        }
+    };
+
+    artifacts::remove_compile_dir(&payload.language, id).await?;
+
+    Ok(Some(compilation))
+}
+
+/// Returns the key of the result of `payload` in the cache, which covers everything the output of
+/// the program depends on. `None` when the image of the language is not built yet.
+async fn cache_key(config: &Config, payload: &Eval, env: &[String]) -> Result<Option<String>> {
+    let Some(image) = image_id(&payload.language).await? else {
+        return Ok(None);
+    };
+    let limits = &config.language;
+
+    Ok(Some(ResultCache::key(&(
+        &payload.language,
+        image,
+        &payload.code,
+        &payload.input,
+        &payload.args,
+        env,
+        (
+            limits.memory,
+            limits.cpus,
+            &limits.runtime,
+            limits.timeout,
+            limits.max_process_count,
+            limits.max_open_files,
+            limits.max_file_size,
+        ),
+    ))))
+}
+
+/// Creates the eval directory and writes the code and input of the eval into it.
+#[tracing::instrument(skip(payload))]
+async fn prepare_eval(payload: &Eval, id: &str) -> Result<()> {
     exec(&[
         "exec",
         &format!("legion-{}", payload.language),
@@ -101,98 +538,81 @@ pub async fn eval(State(config): State<Config>, Json(payload): Json<Eval>) -> Re
     ])
     .await?;
 
-    info!(
-        "[{}] Eval in container {}...",
-        id.yellow(),
-        format!("legion-{}", payload.language).underline().bold()
-    );
-
-    let mut times_failed: u8 = 0;
-    let mut success = false;
-
-    #[allow(clippy::ignored_unit_patterns, unused_assignments)]
-    let output = loop {
-        tokio::select! {
-            _ = sleep(Duration::from_secs_f64(config.language.timeout)) => {
-                exec(&["kill", &format!("legion-{}", payload.language)]).await?;
-                start_container(&payload.language, &config.language).await?;
-
-                return Ok((StatusCode::REQUEST_TIMEOUT, "Eval timed out.".to_string()).into_response())
-            },
-            output = _eval(&payload.language, &payload.code, payload.input.as_deref(), payload.args.as_deref(), &id, config.clone()) => {
-                match output {
-                    Ok(output)  => {
-                        if success || output.status.success() {
-                            success = true;
-                            break output;
-                        }
-
-                        if !success && config.language.retries == times_failed {
-                            break output;
-                        }
-
-                        success = false;
-                        times_failed += 1;
-                    },
-                    Err(err) => {
-                        success = false;
-                        times_failed += 1;
-
-                        if !success && config.language.retries == times_failed {
-                            return Err(err)
-                        }
-                    }
-                }
-            }
-        };
-    };
+    write_file(
+        &payload.language,
+        EVAL_USER,
+        &format!("/tmp/eval/{}/.code", id),
+        format!("{}\n", payload.code).as_bytes(),
+    )
+    .await?;
 
-    exec(&["exec", &format!("legion-{}", payload.language), "rm", "-rf", &format!("eval/{}", id)])
+    // Absent input is distinguishable from empty input: the program's stdin is `/dev/null`
+    // instead of an empty file.
+    if let Some(input) = &payload.input {
+        write_file(
+            &payload.language,
+            EVAL_USER,
+            &format!("/tmp/eval/{}/.input", id),
+            input.as_bytes(),
+        )
         .await?;
+    } else {
+        exec(&[
+            "exec",
+            &format!("-u{}", EVAL_USER),
+            &format!("legion-{}", payload.language),
+            "ln",
+            "-s",
+            "/dev/null",
+            &format!("/tmp/eval/{}/.input", id),
+        ])
+        .await?;
+    }
 
-    info!(
-        "[{}] Finished eval in container {}.",
-        id.yellow(),
-        format!("legion-{}", payload.language).underline().bold()
-    );
-
-    let response = EvalResult {
-        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-        status: EvalStatus {
-            success: output.status.success() || success,
-            code: output.status.code(),
-        },
-    };
-
-    Ok(Json(response).into_response())
+    Ok(())
 }
 
+/// Runs `script` of the language in `dir` as `user`, within the limits of the config.
+///
+/// Sets `pgid` to the process group of the program as soon as it starts.
+#[tracing::instrument(skip(args, env, config, pgid))]
+#[allow(clippy::too_many_arguments)]
 async fn _eval(
     language: &str,
-    code: &str,
-    input: Option<&str>,
+    script: &str,
     args: Option<&[String]>,
-    uid: &str,
+    env: &[String],
+    dir: &str,
+    user: &str,
     config: Config,
+    pgid: Option<&OnceLock<u32>>,
 ) -> Result<Output> {
     let mut cmd = Command::new("docker");
 
+    cmd.args(["exec", &format!("-u{}", user), "-i", &format!("-w{}", dir)]);
+
+    for var in env {
+        cmd.args(["-e", var]);
+    }
+
+    // The program runs in its own session, so that on timeout only its process group is killed
+    // instead of the whole container. Its id is printed before the program starts, so the program
+    // cannot forge it.
     cmd.args([
-        "exec",
-        "-u1001:1001",
-        "-i",
-        &format!("-w/tmp/eval/{}", uid),
         &format!("legion-{}", language),
+        "setsid",
+        "-w",
+        "/bin/sh",
+        "-c",
+        "echo $$ >&2 && exec \"$@\"",
+        "sh",
         "nice",
         "prlimit",
         &format!("--nproc={}", config.language.max_process_count),
         &format!("--nofile={}", config.language.max_open_files),
         &format!("--fsize={}", config.language.max_file_size),
         "/bin/sh",
-        "/var/run/run.sh",
-        code,
-        input.unwrap_or_default(),
+        script,
     ]);
 
     let args = args.unwrap_or_default();
@@ -201,25 +621,119 @@ async fn _eval(
         cmd.args(args);
     }
 
-    Ok(cmd.output().await?)
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let (Some(mut stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(std::io::Error::other("The output of docker exec is not piped").into());
+    };
+    let mut stderr = BufReader::new(stderr);
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+
+    let read_stderr = async {
+        stderr.read_until(b'\n', &mut err).await?;
+
+        // Docker reports failing to start the program in place of the id.
+        if let Some(id) = std::str::from_utf8(&err).ok().and_then(|id| id.trim_end().parse().ok()) {
+            if let Some(pgid) = pgid {
+                let _ = pgid.set(id);
+            }
+
+            err.clear();
+        }
+
+        stderr.read_to_end(&mut err).await
+    };
+
+    tokio::try_join!(stdout.read_to_end(&mut out), read_stderr)?;
+
+    Ok(Output {
+        status: child.wait().await?,
+        stdout: out,
+        stderr: err,
+    })
+}
+
+/// Removes the eval directory of the eval with the provided `id`.
+#[tracing::instrument]
+async fn cleanup_eval(language: &str, id: &str) -> Result<()> {
+    exec(&["exec", &format!("legion-{}", language), "rm", "-rf", &format!("eval/{}", id)]).await?;
+
+    Ok(())
+}
+
+/// Kills the process group `pgid` of an eval, leaving other evals running in the container
+/// untouched.
+#[tracing::instrument]
+async fn kill_eval(language: &str, pgid: u32) -> Result<()> {
+    exec(&[
+        "exec",
+        &format!("-u{}", EVAL_USER),
+        &format!("legion-{}", language),
+        "kill",
+        "-KILL",
+        "--",
+        &format!("-{}", pgid),
+    ])
+    .await?;
+
+    Ok(())
+}
+
+/// Returns whether running a program failed because of docker rather than the program itself.
+fn is_exec_failure(output: &Result<Output>) -> bool {
+    match output {
+        Err(_) => true,
+        Ok(output) => output.status.code().is_some_and(|code| EXEC_FAILURE_CODES.contains(&code)),
+    }
+}
+
+fn validate_env(name: &str) -> std::result::Result<(), String> {
+    let is_valid_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !is_valid_name {
+        return Err(format!("{} is not a valid environment variable name.", name));
+    }
+
+    if DENIED_ENV.contains(&name)
+        || DENIED_ENV_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+    {
+        return Err(format!("The environment variable {} is not allowed.", name));
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::BTreeMap;
     use std::sync::Arc;
 
-    use axum::body::Body;
+    use axum::body::{Body, Bytes};
     use axum::http::{header, Method, Request, StatusCode};
+    use axum::Router;
     use http_body_util::BodyExt;
     use nanoid::nanoid;
     use paste::paste;
     use tokio::fs;
+    use tokio::time::{sleep, Duration};
     use tower::ServiceExt;
 
-    use super::{Eval, EvalResult};
-    use crate::app;
-    use crate::config::{Config, Language};
-    use crate::docker::{build_images, exec, prepare_containers};
+    use super::{is_exec_failure, validate_env, Eval, EvalResult, EvalStatus, DENIED_ENV};
+    use crate::config::{Admin, Cache, Config, Language};
+    use crate::docker::{exec, prepare_containers};
+    use crate::routes::admin::test::admin_app;
+    use crate::supervisor::Supervisor;
+    use crate::{app, AppState};
+
+    /// Languages whose input program prints its input unchanged, so that their output has to
+    /// equal the input byte for byte.
+    const EXACT_ECHO: &[&str] = &["bash", "c", "cpp", "haskell", "perl", "python", "ruby", "rust"];
 
     macro_rules! gen_test {
         ($($name:ident, $ext:expr;)+) => {
@@ -237,10 +751,10 @@ mod test {
                             ..Config::default()
                         });
 
-                        let app = app(config);
+                        let app = app(AppState::new(config).unwrap());
 
                         if option_env!("LEGION_TEST_BUILD").unwrap_or("0") != "0" {
-                            build_images(&[stringify!($name).to_owned()], true).await.expect("Failed building images");
+                            assert!(Supervisor::default().build_images(&[stringify!($name).to_owned()], true).await.is_empty(), "Failed building images");
                         }
 
                         prepare_containers(&[stringify!($name).to_owned()], &Language {
@@ -263,6 +777,8 @@ mod test {
                                             code: fs::read_to_string(format!("test-programs/{}/hello-world{}", stringify!($name).to_owned(), $ext))
                                                 .await.expect("Test program not found"),
                                             args: Some(vec![]),
+                                            env: None,
+                                            cache: None,
                                             input: Some(String::new()),
                                         })
                                         .expect("Failed converting to json string")
@@ -296,10 +812,10 @@ mod test {
                             ..Config::default()
                         });
 
-                        let app = app(config);
+                        let app = app(AppState::new(config).unwrap());
 
                         if option_env!("LEGION_TEST_BUILD").unwrap_or("0") != "0" {
-                            build_images(&[stringify!($name).to_owned()], true).await.expect("Failed building images");
+                            assert!(Supervisor::default().build_images(&[stringify!($name).to_owned()], true).await.is_empty(), "Failed building images");
                         }
 
                         prepare_containers(&[stringify!($name).to_owned()], &Language {
@@ -324,6 +840,8 @@ mod test {
                                             code: fs::read_to_string(format!("test-programs/{}/input{}", stringify!($name).to_owned(), $ext))
                                                 .await.expect("Test program not found"),
                                             args: Some(vec![]),
+                                            env: None,
+                                            cache: None,
                                             input: Some(input.clone()),
                                         })
                                         .expect("Failed converting to json string")
@@ -344,6 +862,75 @@ mod test {
                         exec(&["kill", &format!("legion-{}", stringify!($name))]).await.expect("Failed killing container");
                         exec(&["rm", "-f", "-l", &format!("legion-{}", stringify!($name))]).await.expect("Failed deleting container");
                     }
+
+                    #[tokio::test]
+                    async fn [<$name _exact_input>]() {
+                        if !EXACT_ECHO.contains(&stringify!($name)) {
+                            return;
+                        }
+
+                        let config = Arc::new(Config {
+                            prepare_containers: true,
+                            language: Language {
+                                timeout: 30.0,
+                                enabled: vec![stringify!($name).to_owned()],
+                                ..Language::default()
+                            },
+                            ..Config::default()
+                        });
+
+                        let app = app(AppState::new(config).unwrap());
+
+                        if option_env!("LEGION_TEST_BUILD").unwrap_or("0") != "0" {
+                            assert!(Supervisor::default().build_images(&[stringify!($name).to_owned()], true).await.is_empty(), "Failed building images");
+                        }
+
+                        prepare_containers(&[stringify!($name).to_owned()], &Language {
+                            timeout: 30.0,
+                            enabled: vec![stringify!($name).to_owned()],
+                            ..Language::default()
+                        })
+                        .await
+                        .expect("Failed preparing containers.");
+
+                        // `echo` would interpret the escapes and the leading `-n`, and add another newline.
+                        // The tab and the trailing newline have to arrive as they are.
+                        let input = format!("-n \\t\\\\\t{}\n", nanoid!());
+
+                        let response = app
+                            .oneshot(
+                                Request::builder()
+                                    .method(Method::POST)
+                                    .uri("/api/eval")
+                                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                                    .body(Body::from(
+                                        serde_json::to_string(&Eval {
+                                            language: stringify!($name).to_owned(),
+                                            code: fs::read_to_string(format!("test-programs/{}/input{}", stringify!($name).to_owned(), $ext))
+                                                .await.expect("Test program not found"),
+                                            args: Some(vec![]),
+                                            env: None,
+                                            cache: None,
+                                            input: Some(input.clone()),
+                                        })
+                                        .expect("Failed converting to json string")
+                                    ))
+                                    .unwrap()
+                            )
+                            .await
+                            .unwrap();
+
+                        assert_eq!(response.status(), StatusCode::OK);
+
+                        let body = response.into_body().collect().await.unwrap().to_bytes();
+                        let body: EvalResult = serde_json::from_slice(&body).unwrap();
+
+                        assert_eq!(body.stdout, input, "stderr: {}", body.stderr.trim());
+
+                        // Removing containers as they can cause unwanted clutter in the user's device
+                        exec(&["kill", &format!("legion-{}", stringify!($name))]).await.expect("Failed killing container");
+                        exec(&["rm", "-f", "-l", &format!("legion-{}", stringify!($name))]).await.expect("Failed deleting container");
+                    }
                 }
             )*
         }
@@ -375,4 +962,327 @@ mod test {
         spim, ".s";
         typescript, ".ts";
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn exec_failures_are_told_apart_from_failing_programs() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::{ExitStatus, Output};
+
+        let exited = |code: i32| {
+            Ok(Output {
+                status: ExitStatus::from_raw(code << 8),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            })
+        };
+
+        assert!(!is_exec_failure(&exited(0)));
+        assert!(!is_exec_failure(&exited(1)));
+        assert!(is_exec_failure(&exited(125)));
+        assert!(is_exec_failure(&exited(126)));
+        assert!(is_exec_failure(&Err(anyhow::anyhow!("docker is missing").into())));
+    }
+
+    #[test]
+    fn infrastructure_failures_are_not_cached() {
+        let result = |code: i32, attempts: u16, timed_out: bool| EvalResult {
+            stdout: String::new(),
+            stderr: String::new(),
+            status: EvalStatus {
+                success: code == 0,
+                code: Some(code),
+            },
+            attempts,
+            timed_out,
+            elapsed: 0.0,
+            cached: false,
+        };
+
+        assert!(result(0, 1, false).is_cacheable());
+        assert!(result(0, 2, false).is_cacheable());
+        assert!(result(1, 1, false).is_cacheable());
+        assert!(!result(1, 2, false).is_cacheable());
+        assert!(!result(125, 1, false).is_cacheable());
+        assert!(!result(126, 1, false).is_cacheable());
+        assert!(!result(1, 1, true).is_cacheable());
+    }
+
+    async fn bash_app(timeout: f64) -> Router {
+        bash_app_with_config(timeout, Config::default()).await
+    }
+
+    async fn bash_app_with_config(timeout: f64, config: Config) -> Router {
+        let language = Language {
+            timeout,
+            enabled: vec!["bash".to_owned()],
+            ..Language::default()
+        };
+
+        if option_env!("LEGION_TEST_BUILD").unwrap_or("0") != "0" {
+            assert!(
+                Supervisor::default().build_images(&["bash".to_owned()], true).await.is_empty(),
+                "Failed building images"
+            );
+        }
+
+        prepare_containers(&["bash".to_owned()], &language)
+            .await
+            .expect("Failed preparing containers.");
+
+        app(AppState::new(Arc::new(Config {
+            prepare_containers: true,
+            language,
+            ..config
+        }))
+        .unwrap())
+    }
+
+    async fn send(app: Router, payload: &Eval) -> (StatusCode, Bytes) {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/eval")
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        serde_json::to_string(payload).expect("Failed converting to json string"),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        (status, body)
+    }
+
+    async fn remove_bash_container() {
+        // Removing containers as they can cause unwanted clutter in the user's device
+        exec(&["kill", "legion-bash"]).await.expect("Failed killing container");
+        exec(&["rm", "-f", "-l", "legion-bash"]).await.expect("Failed deleting container");
+    }
+
+    fn bash_payload(code: &str) -> Eval {
+        Eval {
+            language: "bash".to_owned(),
+            code: code.to_owned(),
+            args: None,
+            env: None,
+            input: None,
+            cache: None,
+        }
+    }
+
+    async fn bash_request(payload: &Eval) -> (StatusCode, Bytes) {
+        let response = send(bash_app(30.0).await, payload).await;
+
+        remove_bash_container().await;
+
+        response
+    }
+
+    async fn bash_eval(code: &str, input: Option<String>) -> EvalResult {
+        let (status, body) = bash_request(&Eval {
+            input,
+            ..bash_payload(code)
+        })
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bash_empty_input() {
+        let body = bash_eval("od -An -c", Some(String::new())).await;
+
+        assert_eq!(body.stdout, "", "stderr: {}", body.stderr.trim());
+    }
+
+    #[tokio::test]
+    async fn bash_absent_input() {
+        let body =
+            bash_eval("if [ -c /dev/stdin ]; then echo absent; else echo present; fi", None).await;
+
+        assert_eq!(body.stdout.trim(), "absent", "stderr: {}", body.stderr.trim());
+
+        let body = bash_eval(
+            "if [ -c /dev/stdin ]; then echo absent; else echo present; fi",
+            Some(String::new()),
+        )
+        .await;
+
+        assert_eq!(body.stdout.trim(), "present", "stderr: {}", body.stderr.trim());
+    }
+
+    #[tokio::test]
+    async fn bash_env() {
+        let value = nanoid!();
+        let (status, body) = bash_request(&Eval {
+            env: Some(BTreeMap::from([("GREETING".to_owned(), value.clone())])),
+            ..bash_payload("printf %s \"$GREETING\"")
+        })
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+
+        let body: EvalResult = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body.stdout, value, "stderr: {}", body.stderr.trim());
+    }
+
+    #[test]
+    fn denied_env() {
+        for name in DENIED_ENV.iter().chain(&["LD_PRELOAD", "LD_LIBRARY_PATH", "LD_"]) {
+            assert!(validate_env(name).is_err(), "{} was allowed", name);
+        }
+
+        for name in ["", "1INVALID", "IN=VALID", "IN VALID", "ÄNDERN"] {
+            assert!(validate_env(name).is_err(), "{:?} was allowed", name);
+        }
+
+        for name in ["CPATH", "LANG", "_PRIVATE", "OLD_PATH", "path"] {
+            assert!(validate_env(name).is_ok(), "{} was denied", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn denied_env_is_rejected_before_running() {
+        // The app is lazy, so the request fails if it reaches docker.
+        let (status, _) = send(admin_app(None), &Eval {
+            env: Some(BTreeMap::from([(String::from("LD_PRELOAD"), String::new())])),
+            ..bash_payload("true")
+        })
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn bash_cached_result() {
+        let app = bash_app_with_config(30.0, Config {
+            cache: Cache {
+                enabled: true,
+                ..Cache::default()
+            },
+            ..Config::default()
+        })
+        .await;
+        let payload = bash_payload(&format!("echo {}; echo $RANDOM", nanoid!()));
+
+        let mut results = Vec::new();
+
+        for cache in [None, None, Some(false)] {
+            let (status, body) = send(app.clone(), &Eval {
+                cache,
+                ..payload.clone()
+            })
+            .await;
+
+            assert_eq!(status, StatusCode::OK);
+
+            results.push(serde_json::from_slice::<EvalResult>(&body).unwrap());
+        }
+
+        remove_bash_container().await;
+
+        assert!(!results[0].cached);
+        assert!(results[1].cached);
+        assert_eq!(results[0].stdout, results[1].stdout);
+        assert!(!results[2].cached, "the cache was not bypassed");
+    }
+
+    #[tokio::test]
+    async fn bash_non_zero_exit_is_not_retried() {
+        let body = bash_eval("exit 3", None).await;
+
+        assert!(!body.status.success);
+        assert_eq!(body.status.code, Some(3));
+        assert_eq!(body.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn bash_timeout_only_kills_the_timed_out_eval() {
+        let app = bash_app(3.0).await;
+
+        let timed_out_payload = bash_payload("sleep 30");
+        let survivor_payload = bash_payload("sleep 2 && echo survived");
+
+        let timed_out = send(app.clone(), &timed_out_payload);
+        let survivor = async {
+            // Still running when the first eval times out, but finishes within its own timeout.
+            sleep(Duration::from_secs(2)).await;
+            send(app.clone(), &survivor_payload).await
+        };
+
+        let ((timed_out_status, _), (survivor_status, survivor_body)) =
+            tokio::join!(timed_out, survivor);
+
+        remove_bash_container().await;
+
+        assert_eq!(timed_out_status, StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(survivor_status, StatusCode::OK);
+
+        let body: EvalResult = serde_json::from_slice(&survivor_body).unwrap();
+
+        assert_eq!(body.stdout.trim(), "survived", "stderr: {}", body.stderr.trim());
+    }
+
+    #[tokio::test]
+    async fn bash_timeout_returns_partial_output() {
+        let (status, body) =
+            send(bash_app(2.0).await, &bash_payload("echo partial && sleep 30")).await;
+
+        remove_bash_container().await;
+
+        assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+
+        let body: EvalResult = serde_json::from_slice(&body).unwrap();
+
+        assert!(body.timed_out);
+        assert!(!body.status.success);
+        assert!(body.elapsed >= 2.0);
+        assert_eq!(body.stdout.trim(), "partial", "stderr: {}", body.stderr.trim());
+    }
+
+    #[tokio::test]
+    async fn bash_eval_after_killing_the_container() {
+        let app = bash_app_with_config(30.0, Config {
+            admin: Admin {
+                token: Some(String::from("secret")),
+            },
+            ..Config::default()
+        })
+        .await;
+
+        let killed = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri("/api/containers/bash")
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(killed.status(), StatusCode::NO_CONTENT);
+
+        // The container is started again by the next eval.
+        let (status, body) = send(app, &bash_payload("echo revived")).await;
+
+        remove_bash_container().await;
+
+        assert_eq!(status, StatusCode::OK);
+
+        let body: EvalResult = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body.stdout.trim(), "revived", "stderr: {}", body.stderr.trim());
+    }
 }
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use utoipa::ToSchema;
+
+use crate::docker::{container_exists, image_exists};
+use crate::supervisor::{Readiness, Supervisor};
+use crate::util::is_docker_available;
+use crate::{Config, Result};
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Health {
+    #[schema(example = true)]
+    ready: bool,
+    /// Whether the Docker daemon is reachable.
+    #[schema(example = true)]
+    docker: bool,
+    languages: Vec<LanguageHealth>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct LanguageHealth {
+    #[schema(example = "javascript")]
+    language: String,
+    /// Whether the language can run evals, now or after starting its container on demand.
+    #[schema(example = true)]
+    ready: bool,
+    #[schema(example = true)]
+    image: bool,
+    #[schema(example = true)]
+    container: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "The server is alive.")
+    )
+)]
+pub async fn healthz() -> Response {
+    (StatusCode::OK, "OK").into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, body = Health, description = "The server is ready to run evals."),
+        (status = 503, body = Health, description = "Docker or a language is not ready."),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn readyz(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+) -> Result<Response> {
+    // Docker is not ready when its CLI is missing either.
+    let docker = task::spawn_blocking(is_docker_available).await?.unwrap_or(false);
+
+    let languages = join_all(config.language.enabled.iter().map(|language| {
+        let supervisor = Arc::clone(&supervisor);
+        let config = Arc::clone(&config);
+
+        async move {
+            let (image, container) = if docker {
+                (
+                    image_exists(language).await.unwrap_or(false),
+                    container_exists(language).await.unwrap_or(false),
+                )
+            } else {
+                (false, false)
+            };
+
+            LanguageHealth {
+                language: language.clone(),
+                ready: is_ready(&config, &supervisor.readiness(language), docker, image, container),
+                image,
+                container,
+            }
+        }
+    }))
+    .await;
+
+    let ready = docker && languages.iter().all(|language| language.ready);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    Ok((
+        status,
+        Json(Health {
+            ready,
+            docker,
+            languages,
+        }),
+    )
+        .into_response())
+}
+
+/// Returns whether a language can run evals, now or after starting its container on demand.
+fn is_ready(
+    config: &Config,
+    readiness: &Readiness,
+    docker: bool,
+    image: bool,
+    container: bool,
+) -> bool {
+    // Even a language whose image is built on demand cannot run evals while Docker is unreachable.
+    if !docker {
+        return false;
+    }
+
+    match (container, image, config.lazy) {
+        (true, ..) => true,
+        // The image is built on the first eval, unless that already failed.
+        (false, _, true) => !matches!(readiness, Readiness::Failed(_)),
+        (false, true, false) => config.prepare_containers,
+        (false, false, false) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::{is_ready, Health};
+    use crate::config::{Config, Language};
+    use crate::supervisor::Readiness;
+    use crate::{app, AppState};
+
+    #[tokio::test]
+    async fn healthz() {
+        let response = app(AppState::new(Arc::new(Config::default())).unwrap())
+            .oneshot(
+                Request::builder().method(Method::GET).uri("/healthz").body(Body::empty()).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readyz_reports_languages_that_are_not_ready() {
+        let config = Config {
+            prepare_containers: false,
+            language: Language {
+                enabled: vec![String::from("does-not-exist")],
+                ..Language::default()
+            },
+            ..Config::default()
+        };
+        let response = app(AppState::new(Arc::new(config)).unwrap())
+            .oneshot(
+                Request::builder().method(Method::GET).uri("/readyz").body(Body::empty()).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let health: Health = serde_json::from_slice(&body).unwrap();
+
+        assert!(!health.ready);
+        assert_eq!(health.languages.len(), 1);
+        assert_eq!(health.languages[0].language, "does-not-exist");
+        assert!(!health.languages[0].ready);
+        assert!(!health.languages[0].image);
+    }
+
+    #[test]
+    fn lazy_languages_are_not_ready_without_docker() {
+        let config = Arc::new(Config {
+            lazy: true,
+            ..Config::default()
+        });
+
+        assert!(is_ready(&config, &Readiness::Pending, true, false, false));
+        assert!(!is_ready(&config, &Readiness::Pending, false, false, false));
+        assert!(!is_ready(&config, &Readiness::Failed(String::new()), true, false, false));
+    }
+}
@@ -1,9 +1,63 @@
-use axum::extract::State;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
 
+use crate::routes::not_enabled;
+use crate::supervisor::{Readiness, Supervisor};
+use crate::util::unix_seconds;
 use crate::Config;
 
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct LanguageReadiness {
+    #[schema(example = "javascript")]
+    language: String,
+    status: ReadinessStatus,
+    /// Why building the image failed, when `status` is `failed`.
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadinessStatus {
+    Pending,
+    Building,
+    Ready,
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ImageBuild {
+    #[schema(example = "javascript")]
+    language: String,
+    status: ReadinessStatus,
+    /// Why building the image failed, when `status` is `failed`.
+    error: Option<String>,
+    /// When the last build started, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_000)]
+    started_at: Option<u64>,
+    /// When the last build finished, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_042)]
+    finished_at: Option<u64>,
+}
+
+fn status(readiness: Readiness) -> (ReadinessStatus, Option<String>) {
+    match readiness {
+        Readiness::Pending => (ReadinessStatus::Pending, None),
+        Readiness::Building => (ReadinessStatus::Building, None),
+        Readiness::Ready => (ReadinessStatus::Ready, None),
+        Readiness::Failed(error) => (ReadinessStatus::Failed, Some(error)),
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/api/languages",
@@ -15,3 +69,110 @@ use crate::Config;
 pub async fn languages(State(config): State<Config>) -> Response {
     Json(config.language.enabled.clone()).into_response()
 }
+
+#[utoipa::path(
+    get,
+    path = "/api/languages/readiness",
+    responses(
+        (status = 200, body = Vec<LanguageReadiness>),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn readiness(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+) -> Response {
+    let list = config
+        .language
+        .enabled
+        .iter()
+        .map(|language| {
+            let (status, error) = status(supervisor.readiness(language));
+
+            LanguageReadiness {
+                language: language.clone(),
+                status,
+                error,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Json(list).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/languages/{language}/build",
+    params(("language" = String, Path, description = "The language of the image.", example = "python")),
+    responses(
+        (status = 200, body = ImageBuild),
+        (status = 404, description = "The language is not enabled."),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn build(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Response {
+    if !config.language.enabled.contains(&language) {
+        return not_enabled(&language);
+    }
+
+    let (status, error) = status(supervisor.readiness(&language));
+    let times = supervisor.build_times(&language);
+
+    Json(ImageBuild {
+        language,
+        status,
+        error,
+        started_at: times.started.and_then(unix_seconds),
+        finished_at: times.finished.and_then(unix_seconds),
+    })
+    .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/languages/{language}/build/log",
+    params(("language" = String, Path, description = "The language of the image.", example = "python")),
+    responses(
+        (status = 200, body = String, content_type = "text/plain", description = "The output of the last build. While the build runs, its output is streamed until it finishes."),
+        (status = 404, description = "The language is not enabled."),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn build_log(
+    State(config): State<Config>,
+    State(supervisor): State<Arc<Supervisor>>,
+    Path(language): Path<String>,
+) -> Response {
+    if !config.language.enabled.contains(&language) {
+        return not_enabled(&language);
+    }
+
+    let (lines, receiver) = supervisor.build_log(&language);
+    let running = stream::unfold(receiver, |receiver| async move {
+        let mut receiver = receiver?;
+
+        match receiver.recv().await {
+            Ok(line) => Some((line, Some(receiver))),
+            Err(RecvError::Lagged(skipped)) => {
+                Some((format!("[{} lines skipped]", skipped), Some(receiver)))
+            },
+            Err(RecvError::Closed) => None,
+        }
+    });
+    let body =
+        stream::iter(lines).chain(running).map(|line| Ok::<_, Infallible>(format!("{}\n", line)));
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            // Keeps browsers from buffering the stream to sniff its type.
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
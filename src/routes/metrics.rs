@@ -0,0 +1,54 @@
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+
+use crate::metrics::metrics as registry;
+use crate::Result;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format."),
+        (status = 500, description = "Server error.")
+    )
+)]
+pub async fn metrics() -> Result<Response> {
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], registry().render()?)
+        .into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use crate::config::Config;
+    use crate::metrics::metrics as registry;
+    use crate::{app, AppState};
+
+    #[tokio::test]
+    async fn metrics() {
+        // Metrics are global, so the test counts under a language no other test uses.
+        registry().exec_failures.with_label_values(&["metrics-test"]).inc_by(2);
+        registry().in_flight.with_label_values(&["metrics-test"]).inc();
+
+        let response = app(AppState::new(Arc::new(Config::default())).unwrap())
+            .oneshot(
+                Request::builder().method(Method::GET).uri("/metrics").body(Body::empty()).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("docker_exec_failures_total{language=\"metrics-test\"} 2"));
+        assert!(body.contains("evals_in_flight{language=\"metrics-test\"} 1"));
+    }
+}
@@ -1,4 +1,17 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+pub mod admin;
 pub mod cleanup;
 pub mod containers;
 pub mod eval;
+pub mod health;
 pub mod languages;
+pub mod metrics;
+pub mod submissions;
+
+/// Responds that `language` is not enabled.
+#[must_use]
+pub fn not_enabled(language: &str) -> Response {
+    (StatusCode::NOT_FOUND, format!("The language {} is not enabled.", language)).into_response()
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use tokio::task;
+use utoipa::IntoParams;
+
+use crate::store::{Filter, Store, SubmissionStatus};
+use crate::Result;
+
+/// The maximum number of submissions returned at once.
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Clone, Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmissionQuery {
+    /// Only submissions of this language.
+    #[param(example = "javascript")]
+    language: Option<String>,
+    /// Only submissions received at or after this time, in seconds since the Unix epoch.
+    #[param(example = 1_700_000_000)]
+    since: Option<u64>,
+    /// Only submissions with this status.
+    #[param(inline)]
+    status: Option<SubmissionStatus>,
+    /// The maximum number of submissions to return, at most 1000.
+    #[param(example = 100)]
+    limit: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/submissions",
+    params(SubmissionQuery),
+    responses(
+        (status = 200, body = Vec<Submission>, description = "Submissions, newest first."),
+        (status = 401, description = "The admin token is missing or wrong."),
+        (status = 404, description = "The admin API or the submission store is not enabled."),
+        (status = 500, description = "Server error.")
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn submissions(
+    State(store): State<Option<Arc<Store>>>,
+    Query(query): Query<SubmissionQuery>,
+) -> Result<Response> {
+    let Some(store) = store else {
+        return Ok((StatusCode::NOT_FOUND, "The submission store is not enabled.").into_response());
+    };
+
+    let filter = Filter {
+        language: query.language,
+        since: query.since,
+        status: query.status,
+        limit: query.limit.unwrap_or(100).min(MAX_LIMIT),
+    };
+    let list = task::spawn_blocking(move || store.list(&filter)).await??;
+
+    Ok(Json(list).into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use axum::http::{Method, StatusCode};
+
+    use crate::config::{Config, SubmissionStore};
+    use crate::routes::admin::test::{admin_app, body, request};
+    use crate::AppState;
+
+    #[tokio::test]
+    async fn submissions_are_listed_with_the_admin_token() {
+        let uri = "/api/submissions";
+
+        assert_eq!(
+            body(&admin_app(None), request(Method::GET, uri, Some("secret"))).await,
+            (StatusCode::NOT_FOUND, String::from("The admin API is not enabled."))
+        );
+
+        let app = admin_app(Some("secret"));
+
+        assert_eq!(body(&app, request(Method::GET, uri, None)).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body(&app, request(Method::GET, uri, Some("secret"))).await,
+            (StatusCode::NOT_FOUND, String::from("The submission store is not enabled."))
+        );
+    }
+
+    #[test]
+    fn opening_the_store_fails_without_panicking() {
+        let config = Config {
+            store: SubmissionStore {
+                enabled: true,
+                path: String::from("/nonexistent/submissions.db"),
+                ..SubmissionStore::default()
+            },
+            ..Config::default()
+        };
+
+        let err = AppState::new(Arc::new(config)).unwrap_err();
+
+        assert!(format!("{:?}", err).contains("/nonexistent/submissions.db"));
+    }
+}
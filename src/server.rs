@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Error, Result};
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::{self, Duration};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, info, warn};
+
+use crate::config::{Address, Listener, Tls};
+
+/// How many accepted connections may wait to be served.
+const BACKLOG: usize = 1024;
+
+/// How long to wait before accepting again after accepting failed, e.g. when the process ran out
+/// of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How long a client has to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The stream of an accepted connection, possibly wrapped in TLS.
+trait Io: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Io for T {}
+
+struct Connection {
+    io: Box<dyn Io>,
+    /// The address of the client, which clients of Unix domain sockets do not have.
+    client: Option<SocketAddr>,
+}
+
+enum Bound {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Bound {
+    async fn accept(&self) -> std::io::Result<Connection> {
+        match self {
+            Bound::Tcp(listener) => {
+                let (stream, client) = listener.accept().await?;
+
+                Ok(Connection {
+                    io: Box::new(stream),
+                    client: Some(client),
+                })
+            },
+            #[cfg(unix)]
+            Bound::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+
+                Ok(Connection {
+                    io: Box::new(stream),
+                    client: None,
+                })
+            },
+        }
+    }
+}
+
+/// Creates the acceptor for HTTPS from the PEM files of `tls`.
+fn acceptor(tls: &Tls) -> Result<TlsAcceptor> {
+    let mut cert = BufReader::new(
+        File::open(&tls.cert).with_context(|| format!("Opening {} failed", tls.cert))?,
+    );
+    let mut key = BufReader::new(
+        File::open(&tls.key).with_context(|| format!("Opening {} failed", tls.key))?,
+    );
+
+    let certs = rustls_pemfile::certs(&mut cert)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Reading the certificates in {} failed", tls.cert))?;
+    let key = rustls_pemfile::private_key(&mut key)
+        .with_context(|| format!("Reading the private key in {} failed", tls.key))?
+        .with_context(|| format!("{} has no private key", tls.key))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+async fn bind(address: &Address) -> Result<Bound> {
+    match address {
+        Address::Tcp(address) => Ok(Bound::Tcp(
+            TcpListener::bind(address)
+                .await
+                .with_context(|| format!("Listening on {} failed", address))?,
+        )),
+        #[cfg(unix)]
+        Address::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+
+            // A socket left behind by a previous run would make binding fail.
+            if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                std::fs::remove_file(path)?;
+            }
+
+            Ok(Bound::Unix(
+                UnixListener::bind(path)
+                    .with_context(|| format!("Listening on {} failed", path))?,
+            ))
+        },
+        #[cfg(not(unix))]
+        Address::Unix(_) => {
+            anyhow::bail!("Unix domain sockets are not supported on this platform")
+        },
+    }
+}
+
+/// Accepts connections on `bound`, doing the TLS handshake if there is an `acceptor`.
+async fn accept(
+    bound: Bound,
+    acceptor: Option<TlsAcceptor>,
+    connections: mpsc::Sender<Connection>,
+) {
+    loop {
+        let connection = match bound.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                warn!(error = %err, "Accepting a connection failed.");
+
+                time::sleep(ACCEPT_BACKOFF).await;
+
+                continue;
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let connections = connections.clone();
+
+        // The handshake runs in its own task, so that a slow client does not hold up others.
+        tokio::spawn(async move {
+            let connection = match acceptor {
+                Some(acceptor) => {
+                    match time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(connection.io)).await
+                    {
+                        Ok(Ok(stream)) => Connection {
+                            io: Box::new(stream),
+                            ..connection
+                        },
+                        Ok(Err(err)) => {
+                            debug!(error = %err, "TLS handshake failed.");
+
+                            return;
+                        },
+                        Err(_) => {
+                            debug!("TLS handshake timed out.");
+
+                            return;
+                        },
+                    }
+                },
+                None => connection,
+            };
+
+            let _ = connections.send(connection).await;
+        });
+    }
+}
+
+/// Serves `app` on every listener in `listeners` until `shutdown` completes, then waits for the
+/// open connections to finish their requests.
+///
+/// # Errors
+///
+/// - When an address is invalid or cannot be listened on.
+/// - When reading the certificate or private key of a listener fails.
+pub async fn serve(
+    app: Router,
+    listeners: &[Listener],
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let (sender, mut connections) = mpsc::channel(BACKLOG);
+    let mut accepting = JoinSet::new();
+    let mut sockets = Vec::new();
+
+    for listener in listeners {
+        let address = listener.address().map_err(Error::msg)?;
+        let acceptor = listener.tls.as_ref().map(acceptor).transpose()?;
+        let bound = bind(&address).await?;
+
+        info!(address = listener.address, tls = acceptor.is_some(), "Listening.");
+
+        if let Address::Unix(path) = address {
+            sockets.push(path);
+        }
+
+        accepting.spawn(accept(bound, acceptor, sender.clone()));
+    }
+
+    drop(sender);
+
+    let builder = Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+
+    tokio::pin!(shutdown);
+
+    loop {
+        let connection = tokio::select! {
+            connection = connections.recv() => connection,
+            () = &mut shutdown => None,
+        };
+
+        let Some(Connection {
+            io,
+            client,
+        }) = connection
+        else {
+            break;
+        };
+
+        let app = app.clone();
+        let service = service_fn(move |mut request: Request<Incoming>| {
+            if let Some(client) = client {
+                request.extensions_mut().insert(ConnectInfo(client));
+            }
+
+            app.clone().call(request)
+        });
+
+        let connection = graceful
+            .watch(builder.serve_connection_with_upgrades(TokioIo::new(io), service).into_owned());
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!(error = %err, "Serving a connection failed.");
+            }
+        });
+    }
+
+    accepting.shutdown().await;
+    graceful.shutdown().await;
+
+    for path in sockets {
+        let _ = std::fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpStream, UnixStream};
+    use tokio::sync::oneshot;
+    use tokio_rustls::rustls::crypto::ring;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    use super::serve;
+    use crate::config::{Config, Listener, Tls};
+    use crate::{app, AppState};
+
+    /// Returns the path of a file of the test certificate authority, which signed a certificate
+    /// for `localhost`.
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+            .display()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn serves_on_unix_domain_sockets() {
+        let path = std::env::temp_dir().join(format!("legion-{}.sock", std::process::id()));
+        let listeners = [Listener {
+            address: format!("unix:{}", path.display()),
+            tls: None,
+        }];
+        let (stop, stopped) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            serve(app(AppState::new(Arc::new(Config::default())).unwrap()), &listeners, async {
+                let _ = stopped.await;
+            })
+            .await
+        });
+
+        let mut stream = loop {
+            if let Ok(stream) = UnixStream::connect(&path).await {
+                break stream;
+            }
+
+            tokio::task::yield_now().await;
+        };
+
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: legion\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("OK"));
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn serves_https() {
+        // Binding to port 0 picks a free port, which the server listens on after it is released.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let listeners = [Listener {
+            address: format!("127.0.0.1:{}", port),
+            tls: Some(Tls {
+                cert: fixture("cert.pem"),
+                key: fixture("key.pem"),
+            }),
+        }];
+        let (stop, stopped) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            serve(app(AppState::new(Arc::new(Config::default())).unwrap()), &listeners, async {
+                let _ = stopped.await;
+            })
+            .await
+        });
+
+        let mut roots = RootCertStore::empty();
+
+        for cert in
+            rustls_pemfile::certs(&mut BufReader::new(File::open(fixture("ca.pem")).unwrap()))
+        {
+            roots.add(cert.unwrap()).unwrap();
+        }
+
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let stream = loop {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                break stream;
+            }
+
+            tokio::task::yield_now().await;
+        };
+
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("OK"));
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, Row, ToSql};
+use serde::{Deserialize, Serialize};
+use tokio::{task, time};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::util::unix_seconds;
+
+/// How often submissions older than the retention are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// An eval and its result, as kept in the store.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Submission {
+    #[schema(example = "V1StGXR8_Z5jdHi6B-myT")]
+    pub id: String,
+    /// When the eval was received, in seconds since the Unix epoch.
+    #[schema(example = 1_700_000_000)]
+    pub created_at: u64,
+    #[schema(example = "javascript")]
+    pub language: String,
+    #[schema(example = "console.log('Hello, World!');")]
+    pub code: String,
+    pub input: Option<String>,
+    pub args: Option<Vec<String>>,
+    #[schema(example = "Hello, World!")]
+    pub stdout: String,
+    pub stderr: String,
+    pub status: SubmissionStatus,
+    #[schema(example = 0)]
+    pub exit_code: Option<i32>,
+    /// The number of times the program was run.
+    #[schema(example = 1)]
+    pub attempts: u16,
+    /// Time in seconds the program ran for. Absent when the eval errored before it ran.
+    #[schema(example = 0.25)]
+    pub duration: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubmissionStatus {
+    /// The program exited with code 0.
+    Success,
+    /// The program exited with a non-zero code.
+    Failure,
+    /// The program was killed for exceeding the timeout.
+    Timeout,
+    /// Running the program failed.
+    Error,
+}
+
+/// Which submissions to list, newest first.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub language: Option<String>,
+    /// Only submissions received at or after this time, in seconds since the Unix epoch.
+    pub since: Option<u64>,
+    pub status: Option<SubmissionStatus>,
+    pub limit: u32,
+}
+
+/// Submissions and their results, kept in a `SQLite` database.
+#[derive(Debug)]
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+impl SubmissionStatus {
+    /// Returns the status of an eval from its outcome, as counted in the metrics.
+    #[must_use]
+    pub fn from_outcome(outcome: &str) -> Self {
+        match outcome {
+            "success" => Self::Success,
+            "failure" => Self::Failure,
+            "timeout" => Self::Timeout,
+            _ => Self::Error,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+            Self::Timeout => "timeout",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl ToSql for SubmissionStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for SubmissionStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "success" => Ok(Self::Success),
+            "failure" => Ok(Self::Failure),
+            "timeout" => Ok(Self::Timeout),
+            "error" => Ok(Self::Error),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// - When opening or migrating the database fails.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a database that only lives in memory.
+    ///
+    /// # Errors
+    ///
+    /// - When creating the database fails.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS submissions (
+                id TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                language TEXT NOT NULL,
+                code TEXT NOT NULL,
+                input TEXT,
+                args TEXT,
+                stdout TEXT NOT NULL,
+                stderr TEXT NOT NULL,
+                status TEXT NOT NULL,
+                exit_code INTEGER,
+                attempts INTEGER NOT NULL,
+                duration REAL
+            );
+            CREATE INDEX IF NOT EXISTS submissions_created_at ON submissions (created_at);
+            CREATE INDEX IF NOT EXISTS submissions_language ON submissions (language);",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Saves `submission`.
+    ///
+    /// # Errors
+    ///
+    /// - When writing to the database fails.
+    pub fn insert(&self, submission: &Submission) -> Result<()> {
+        let args = submission.args.as_ref().map(serde_json::to_string).transpose()?;
+
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO submissions (id, created_at, language, code, input, args, stdout, stderr, \
+             status, exit_code, attempts, duration)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                submission.id,
+                submission.created_at,
+                submission.language,
+                submission.code,
+                submission.input,
+                args,
+                submission.stdout,
+                submission.stderr,
+                submission.status,
+                submission.exit_code,
+                submission.attempts,
+                submission.duration,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Lists the submissions matching `filter`, newest first.
+    ///
+    /// # Errors
+    ///
+    /// - When reading from the database fails.
+    pub fn list(&self, filter: &Filter) -> Result<Vec<Submission>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, created_at, language, code, input, args, stdout, stderr, status, \
+             exit_code, attempts, duration
+             FROM submissions
+             WHERE (?1 IS NULL OR language = ?1)
+                AND (?2 IS NULL OR created_at >= ?2)
+                AND (?3 IS NULL OR status = ?3)
+             ORDER BY created_at DESC, rowid DESC
+             LIMIT ?4",
+        )?;
+
+        let submissions = statement
+            .query_map(
+                params![filter.language, filter.since, filter.status, filter.limit],
+                submission_from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(submissions)
+    }
+
+    /// Deletes the submissions received before `before`, in seconds since the Unix epoch.
+    ///
+    /// Returns the number of deleted submissions.
+    ///
+    /// # Errors
+    ///
+    /// - When writing to the database fails.
+    pub fn prune(&self, before: u64) -> Result<usize> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM submissions WHERE created_at < ?1", params![before])?)
+    }
+}
+
+fn submission_from_row(row: &Row<'_>) -> rusqlite::Result<Submission> {
+    let args: Option<String> = row.get(5)?;
+
+    Ok(Submission {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        language: row.get(2)?,
+        code: row.get(3)?,
+        input: row.get(4)?,
+        args: args.and_then(|args| serde_json::from_str(&args).ok()),
+        stdout: row.get(6)?,
+        stderr: row.get(7)?,
+        status: row.get(8)?,
+        exit_code: row.get(9)?,
+        attempts: row.get(10)?,
+        duration: row.get(11)?,
+    })
+}
+
+/// Periodically deletes submissions older than `retention` days.
+pub async fn run(store: Arc<Store>, retention: f64) {
+    let mut interval = time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let before = SystemTime::now()
+            .checked_sub(Duration::from_secs_f64(retention * 24.0 * 60.0 * 60.0))
+            .and_then(unix_seconds)
+            .unwrap_or_default();
+        let store = Arc::clone(&store);
+
+        match task::spawn_blocking(move || store.prune(before)).await {
+            Ok(Ok(0)) => {},
+            Ok(Ok(deleted)) => info!(deleted, "Deleted submissions past their retention."),
+            Ok(Err(err)) => error!(error = %err, "Deleting old submissions failed."),
+            Err(err) => error!(error = %err, "Deleting old submissions panicked."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Filter, Store, Submission, SubmissionStatus};
+
+    fn submission(
+        id: &str,
+        created_at: u64,
+        language: &str,
+        status: SubmissionStatus,
+    ) -> Submission {
+        Submission {
+            id: id.to_owned(),
+            created_at,
+            language: language.to_owned(),
+            code: String::from("echo hi"),
+            input: None,
+            args: Some(vec![String::from("--flag")]),
+            stdout: String::from("hi\n"),
+            stderr: String::new(),
+            status,
+            exit_code: Some(0),
+            attempts: 1,
+            duration: Some(0.1),
+        }
+    }
+
+    #[test]
+    fn submissions_are_filtered_and_pruned() {
+        let store = Store::open_in_memory().unwrap();
+
+        store.insert(&submission("a", 100, "bash", SubmissionStatus::Success)).unwrap();
+        store.insert(&submission("b", 200, "python", SubmissionStatus::Timeout)).unwrap();
+        store.insert(&submission("c", 300, "bash", SubmissionStatus::Failure)).unwrap();
+
+        let ids = |filter: Filter| {
+            store
+                .list(&Filter {
+                    limit: 100,
+                    ..filter
+                })
+                .unwrap()
+                .into_iter()
+                .map(|submission| submission.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(Filter::default()), ["c", "b", "a"]);
+        assert_eq!(
+            ids(Filter {
+                language: Some(String::from("bash")),
+                ..Filter::default()
+            }),
+            ["c", "a"]
+        );
+        assert_eq!(
+            ids(Filter {
+                since: Some(200),
+                ..Filter::default()
+            }),
+            ["c", "b"]
+        );
+        assert_eq!(
+            ids(Filter {
+                status: Some(SubmissionStatus::Timeout),
+                ..Filter::default()
+            }),
+            ["b"]
+        );
+
+        assert_eq!(store.prune(200).unwrap(), 1);
+        assert_eq!(ids(Filter::default()), ["c", "b"]);
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::{
+    broadcast,
+    Mutex as AsyncMutex,
+    MutexGuard,
+    OnceCell,
+    OwnedRwLockReadGuard,
+    OwnedRwLockWriteGuard,
+    RwLock,
+};
+use tokio::time::{self, timeout, Duration};
+use tracing::{error, info, warn};
+
+use crate::config::Reap;
+use crate::docker::{
+    build_image_with_log,
+    container_exists,
+    exec,
+    kill_container,
+    restart_container,
+    start_container,
+};
+use crate::metrics::metrics;
+use crate::{Config, SharedConfig};
+
+/// How long a container has to answer a health check exec.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many lines of a running build a slow subscriber of its log may fall behind.
+const BUILD_LOG_CAPACITY: usize = 1024;
+
+/// Tracks the usage of the language containers, so that they are only recycled when no eval is
+/// running in them.
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    containers: Mutex<HashMap<String, Arc<Container>>>,
+    /// Held while the config is changed, so that reloads and changes through the admin API do not
+    /// overwrite each other.
+    updating: AsyncMutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct Container {
+    /// Held for reading by every eval and for writing while the container is recycled.
+    lock: Arc<RwLock<()>>,
+    /// Held while the container is started, so that concurrent evals only start it once.
+    starting: AsyncMutex<()>,
+    /// Set once the image is built.
+    image: OnceCell<()>,
+    /// Held while the image is built, so that builds of a language do not overlap.
+    building: AsyncMutex<()>,
+    /// Held while an eval is compiled ahead of its run, so that no other compilation can tamper
+    /// with its output before it is cached.
+    compiling: AsyncMutex<()>,
+    build: Mutex<Build>,
+    readiness: Mutex<Readiness>,
+    stats: Mutex<Stats>,
+    /// Set while the language is disabled, so that evals that waited for their lease while it
+    /// was retired do not start its container again.
+    retired: AtomicBool,
+}
+
+impl Container {
+    /// Starts recording a new build, discarding the log of the last one.
+    fn begin_build(&self) {
+        *self.readiness.lock().unwrap() = Readiness::Building;
+        *self.build.lock().unwrap() = Build {
+            lines: Vec::new(),
+            started: Some(SystemTime::now()),
+            finished: None,
+            sender: Some(broadcast::channel(BUILD_LOG_CAPACITY).0),
+        };
+    }
+
+    /// Records a line of the output of the running build, sending it to the subscribers of its
+    /// log.
+    fn log_build(&self, line: &str) {
+        let mut build = self.build.lock().unwrap();
+
+        if let Some(sender) = &build.sender {
+            let _ = sender.send(line.to_owned());
+        }
+
+        build.lines.push(line.to_owned());
+    }
+
+    /// Stops recording the running build, ending the streams of its log.
+    fn end_build(&self) {
+        let mut build = self.build.lock().unwrap();
+
+        build.finished = Some(SystemTime::now());
+        build.sender = None;
+    }
+}
+
+/// The last image build of a language.
+#[derive(Debug, Default)]
+struct Build {
+    lines: Vec<String>,
+    started: Option<SystemTime>,
+    finished: Option<SystemTime>,
+    /// Sends the lines of the running build to the subscribers of its log. It is dropped when
+    /// the build finishes, which ends their streams.
+    sender: Option<broadcast::Sender<String>>,
+}
+
+/// When the last image build of a language ran.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuildTimes {
+    pub started: Option<SystemTime>,
+    pub finished: Option<SystemTime>,
+}
+
+/// Whether a language can run evals.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Readiness {
+    /// The image is not built yet, which happens on the first eval in lazy mode.
+    #[default]
+    Pending,
+    /// The image is being built.
+    Building,
+    /// The image is built.
+    Ready,
+    /// Building the image failed. In lazy mode, it is built again on the next eval.
+    Failed(String),
+}
+
+/// Usage statistics of a language container.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// The number of evals currently running in the container.
+    pub in_flight: usize,
+    /// The number of evals run since the container was last recycled.
+    pub evals: u64,
+    /// When the last eval finished.
+    pub last_used: Option<SystemTime>,
+    /// Whether the container needs recycling, e.g. after it failed a health check.
+    pub dirty: bool,
+    /// Whether the container was reaped for being idle, and is left stopped until its next eval.
+    pub reaped: bool,
+}
+
+/// Permission to run an eval in a container, which is not recycled while the lease is held.
+#[derive(Debug)]
+pub struct Lease {
+    language: String,
+    container: Arc<Container>,
+    _guard: OwnedRwLockReadGuard<()>,
+}
+
+impl Supervisor {
+    fn container(&self, language: &str) -> Arc<Container> {
+        let mut containers = self.containers.lock().unwrap();
+
+        Arc::clone(containers.entry(language.to_owned()).or_default())
+    }
+
+    /// Leases the container of `language` for an eval, waiting for it to finish recycling.
+    pub async fn lease(&self, language: &str) -> Lease {
+        let container = self.container(language);
+        let guard = Arc::clone(&container.lock).read_owned().await;
+
+        let mut stats = container.stats.lock().unwrap();
+
+        stats.in_flight += 1;
+        stats.reaped = false;
+
+        drop(stats);
+
+        metrics().in_flight.with_label_values(&[language]).inc();
+
+        Lease {
+            language: language.to_owned(),
+            container,
+            _guard: guard,
+        }
+    }
+
+    /// Returns whether `language` can run evals.
+    pub fn readiness(&self, language: &str) -> Readiness {
+        self.container(language).readiness.lock().unwrap().clone()
+    }
+
+    /// Marks the image of `language` as built.
+    pub fn mark_ready(&self, language: &str) {
+        let container = self.container(language);
+
+        let _ = container.image.set(());
+        *container.readiness.lock().unwrap() = Readiness::Ready;
+    }
+
+    /// Makes sure the container of `language` is running, starting it if allowed. In lazy mode,
+    /// the image is built first if it was not yet, with concurrent evals waiting on one build.
+    ///
+    /// # Errors
+    ///
+    /// - When building the image fails.
+    /// - When the language was retired.
+    /// - When the container is not running and may not be started.
+    pub async fn ensure_container(&self, language: &str, config: &Config) -> Result<()> {
+        let container = self.container(language);
+
+        if container.retired.load(Ordering::SeqCst) {
+            bail!("The language {} is not enabled.", language);
+        }
+
+        if config.lazy {
+            container.image.get_or_try_init(|| self.build(language, config.update_images)).await?;
+        } else if let Readiness::Failed(err) = self.readiness(language) {
+            bail!("Building the image of {} failed: {}", language, err);
+        }
+
+        let _starting = container.starting.lock().await;
+
+        if container_exists(language).await? {
+            return Ok(());
+        }
+
+        if config.lazy || config.prepare_containers {
+            warn!(language, "Container is not present. Starting a new container.");
+
+            start_container(language, &config.language).await?;
+        } else {
+            error!(language, "Container is not present.");
+
+            bail!("Container legion-{} does not exist.", language);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the image of `language` unless it is present and `update_images` is `false`,
+    /// recording its output for [`Supervisor::build_log`]. Waits for a running build of
+    /// `language` to finish first.
+    ///
+    /// # Errors
+    ///
+    /// - When building the image fails.
+    pub async fn build(&self, language: &str, update_images: bool) -> Result<()> {
+        let container = self.container(language);
+        let _building = container.building.lock().await;
+
+        container.begin_build();
+
+        let result =
+            build_image_with_log(language, update_images, |line| container.log_build(line)).await;
+
+        container.end_build();
+
+        match &result {
+            Ok(()) => self.mark_ready(language),
+            Err(err) => *container.readiness.lock().unwrap() = Readiness::Failed(err.to_string()),
+        }
+
+        Ok(result?)
+    }
+
+    /// Builds the images of `languages` concurrently, returning the languages whose build
+    /// failed. The other languages are marked ready.
+    pub async fn build_images(&self, languages: &[String], update_images: bool) -> Vec<String> {
+        info!("Building images...");
+
+        let failed = stream::iter(languages)
+            .map(|language| async move {
+                let result = self.build(language, update_images).await;
+
+                if let Err(err) = &result {
+                    error!(language, error = %err, "Building image failed.");
+                }
+
+                result.is_err().then(|| language.clone())
+            })
+            .buffer_unordered(10)
+            .filter_map(|failed| async move { failed })
+            .collect::<Vec<_>>()
+            .await;
+
+        info!("Finished building images.");
+
+        failed
+    }
+
+    /// Returns when the last image build of `language` ran.
+    pub fn build_times(&self, language: &str) -> BuildTimes {
+        let container = self.container(language);
+        let build = container.build.lock().unwrap();
+
+        BuildTimes {
+            started: build.started,
+            finished: build.finished,
+        }
+    }
+
+    /// Returns the output of the last image build of `language` so far, and a receiver of its
+    /// further output if the build is still running.
+    pub fn build_log(&self, language: &str) -> (Vec<String>, Option<broadcast::Receiver<String>>) {
+        let container = self.container(language);
+        let build = container.build.lock().unwrap();
+
+        (build.lines.clone(), build.sender.as_ref().map(broadcast::Sender::subscribe))
+    }
+
+    /// Returns the usage statistics of the container of `language`.
+    pub fn stats(&self, language: &str) -> Stats {
+        *self.container(language).stats.lock().unwrap()
+    }
+
+    /// Marks the container of `language` as needing recycling.
+    pub fn mark_dirty(&self, language: &str) {
+        self.container(language).stats.lock().unwrap().dirty = true;
+    }
+
+    /// Locks the container of `language` for recycling, unless an eval is running in it.
+    fn try_recycle(&self, language: &str) -> Option<Recycle> {
+        let container = self.container(language);
+        let guard = Arc::clone(&container.lock).try_write_owned().ok()?;
+
+        Some(Recycle {
+            container,
+            _guard: guard,
+        })
+    }
+
+    /// Recycles the running container of `language` if no eval is running in it.
+    ///
+    /// Returns whether the container was recycled.
+    ///
+    /// # Errors
+    ///
+    /// - When restarting the container fails.
+    pub async fn recycle(&self, language: &str, config: &Config) -> Result<bool> {
+        let Some(recycle) = self.try_recycle(language) else {
+            return Ok(false);
+        };
+
+        if !container_exists(language).await? {
+            return Ok(false);
+        }
+
+        restart_container(language, &config.language).await?;
+
+        let mut stats = recycle.container.stats.lock().unwrap();
+
+        stats.evals = 0;
+        stats.dirty = false;
+
+        Ok(true)
+    }
+
+    /// Kills the container of `language` if no eval is running in it and it has been idle or
+    /// run evals for longer than its [`Reap`] policy allows. `since` is when it counts as idle
+    /// from if it never ran an eval.
+    ///
+    /// Returns whether the container was reaped.
+    ///
+    /// # Errors
+    ///
+    /// - When killing the container fails.
+    pub async fn reap(&self, language: &str, config: &Config, since: SystemTime) -> Result<bool> {
+        let Reap {
+            idle,
+            max_evals,
+        } = config.reap_policy(language);
+        let stats = self.stats(language);
+        let idle_for = SystemTime::now().duration_since(stats.last_used.unwrap_or(since));
+
+        let is_idle = idle.is_some_and(|idle| {
+            idle_for.is_ok_and(|idle_for| idle_for >= Duration::from_secs_f64(idle * 60.0))
+        });
+        let is_used_up = max_evals.is_some_and(|max_evals| stats.evals >= max_evals);
+
+        if stats.reaped || !(is_idle || is_used_up) {
+            return Ok(false);
+        }
+
+        let Some(recycle) = self.try_recycle(language) else {
+            return Ok(false);
+        };
+
+        if !container_exists(language).await? {
+            return Ok(false);
+        }
+
+        kill_container(language).await?;
+
+        let mut stats = recycle.container.stats.lock().unwrap();
+
+        stats.evals = 0;
+        stats.dirty = false;
+        stats.reaped = true;
+
+        Ok(true)
+    }
+
+    /// Kills the container of `language` once the evals running in it finish, leaving it stopped
+    /// until its next eval.
+    ///
+    /// # Errors
+    ///
+    /// - When killing the container fails.
+    pub async fn kill(&self, language: &str) -> Result<()> {
+        let container = self.container(language);
+        let _guard = Arc::clone(&container.lock).write_owned().await;
+
+        if container_exists(language).await? {
+            kill_container(language).await?;
+        }
+
+        let mut stats = container.stats.lock().unwrap();
+
+        stats.evals = 0;
+        stats.dirty = false;
+        stats.reaped = true;
+
+        Ok(())
+    }
+
+    /// Kills the container of the disabled `language` once the evals running in it finish, leaving
+    /// it stopped until the language is enabled again with [`Supervisor::enable`].
+    ///
+    /// # Errors
+    ///
+    /// - When killing the container fails.
+    pub async fn retire(&self, language: &str) -> Result<()> {
+        // Evals that wait for their lease meanwhile do not start the container again.
+        self.container(language).retired.store(true, Ordering::SeqCst);
+
+        self.kill(language).await
+    }
+
+    /// Lets evals start the container of a retired `language` again.
+    pub fn enable(&self, language: &str) {
+        self.container(language).retired.store(false, Ordering::SeqCst);
+    }
+
+    /// Waits for other changes of the config to be applied, then locks the config for changing.
+    pub async fn lock_config(&self) -> MutexGuard<'_, ()> {
+        self.updating.lock().await
+    }
+
+    /// Restarts the container of `language` once the evals running in it finish, starting it if
+    /// it is not running.
+    ///
+    /// # Errors
+    ///
+    /// - When restarting the container fails.
+    pub async fn restart(&self, language: &str, config: &Config) -> Result<()> {
+        let container = self.container(language);
+        let _guard = Arc::clone(&container.lock).write_owned().await;
+
+        restart_container(language, &config.language).await?;
+
+        let mut stats = container.stats.lock().unwrap();
+
+        stats.evals = 0;
+        stats.dirty = false;
+        stats.reaped = false;
+
+        Ok(())
+    }
+
+    /// Builds the image of `language` again, then restarts its container on the new image if it
+    /// is running.
+    ///
+    /// # Errors
+    ///
+    /// - When building the image fails.
+    /// - When restarting the container fails.
+    pub async fn rebuild(&self, language: &str, config: &Config) -> Result<()> {
+        self.build(language, true).await?;
+
+        if container_exists(language).await? {
+            self.restart(language, config).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Health checks the container of `language`, restarting it when it is missing and marking
+    /// it dirty when it is unresponsive or uses too much disk.
+    async fn check(&self, language: &str, config: &Config) -> Result<()> {
+        if !container_exists(language).await? {
+            if config.prepare_containers
+                && !self.stats(language).reaped
+                && self.readiness(language) == Readiness::Ready
+            {
+                warn!(language, "Container is not running. Starting it.");
+
+                start_container(language, &config.language).await?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(reason) = unhealthy_reason(language, config).await? {
+            warn!(language, reason, "Container is unhealthy.");
+
+            self.mark_dirty(language);
+        }
+
+        if self.stats(language).dirty && self.recycle(language, config).await? {
+            info!(language, "Recycled dirty container.");
+        }
+
+        Ok(())
+    }
+}
+
+impl Lease {
+    /// Marks the leased container as needing recycling once it is idle.
+    pub fn mark_dirty(&self) {
+        self.container.stats.lock().unwrap().dirty = true;
+    }
+
+    /// Waits for the other evals compiling in the leased container to finish, then locks it for
+    /// compiling.
+    pub async fn lock_compilation(&self) -> MutexGuard<'_, ()> {
+        self.container.compiling.lock().await
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut stats = self.container.stats.lock().unwrap();
+
+        stats.in_flight -= 1;
+        stats.evals += 1;
+        stats.last_used = Some(SystemTime::now());
+
+        metrics().in_flight.with_label_values(&[&self.language]).dec();
+    }
+}
+
+struct Recycle {
+    container: Arc<Container>,
+    _guard: OwnedRwLockWriteGuard<()>,
+}
+
+/// Returns why the running container of `language` is unhealthy, if it is.
+async fn unhealthy_reason(language: &str, config: &Config) -> Result<Option<String>> {
+    let container = format!("legion-{}", language);
+
+    let Ok(output) = timeout(HEALTH_CHECK_TIMEOUT, exec(&["exec", &container, "true"])).await
+    else {
+        return Ok(Some(String::from("it does not respond")));
+    };
+
+    if !output?.status.success() {
+        return Ok(Some(String::from("running a command in it fails")));
+    }
+
+    let output = exec(&["exec", &container, "du", "-sm", "/tmp"]).await?;
+    let disk_usage = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .and_then(|usage| usage.parse::<u32>().ok());
+
+    match disk_usage {
+        Some(usage) if usage > config.language.max_disk_usage => Ok(Some(format!(
+            "it uses {}MB of disk, more than the limit of {}MB",
+            usage, config.language.max_disk_usage
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// Periodically reaps idle containers, and health checks the others, following the current
+/// config as it is reloaded.
+pub async fn run(config: SharedConfig, supervisor: Arc<Supervisor>) {
+    let started = SystemTime::now();
+
+    loop {
+        time::sleep(Duration::from_secs_f64(config.load().health_check_interval)).await;
+
+        let config = config.load_full();
+
+        for language in &config.language.enabled {
+            match supervisor.reap(language, &config, started).await {
+                Ok(true) => {
+                    info!(language, "Reaped idle container.");
+                },
+                Ok(false) => {
+                    if let Err(err) = supervisor.check(language, &config).await {
+                        error!(language, error = %err, "Health checking container failed.");
+                    }
+                },
+                Err(err) => error!(language, error = %err, "Reaping container failed."),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::UNIX_EPOCH;
+
+    use super::{Readiness, Supervisor};
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn containers_are_not_recycled_while_leased() {
+        let supervisor = Supervisor::default();
+        let lease = supervisor.lease("bash").await;
+
+        assert_eq!(supervisor.stats("bash").in_flight, 1);
+        assert!(supervisor.try_recycle("bash").is_none());
+
+        drop(lease);
+
+        let stats = supervisor.stats("bash");
+
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.evals, 1);
+        assert!(stats.last_used.is_some());
+        assert!(supervisor.try_recycle("bash").is_some());
+    }
+
+    #[tokio::test]
+    async fn containers_are_not_reaped_while_leased() {
+        let supervisor = Supervisor::default();
+        let config = Arc::new(Config {
+            cleanup_interval: 0.0,
+            ..Config::default()
+        });
+        let _lease = supervisor.lease("bash").await;
+
+        assert!(!supervisor.reap("bash", &config, UNIX_EPOCH).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn retired_languages_are_not_started() {
+        let supervisor = Supervisor::default();
+        let container = supervisor.container("bash");
+        let config = Arc::new(Config::default());
+
+        container.retired.store(true, Ordering::SeqCst);
+
+        let _lease = supervisor.lease("bash").await;
+
+        assert!(supervisor.ensure_container("bash", &config).await.is_err());
+
+        supervisor.enable("bash");
+
+        assert!(!container.retired.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn languages_are_pending_until_marked_ready() {
+        let supervisor = Supervisor::default();
+
+        assert_eq!(supervisor.readiness("bash"), Readiness::Pending);
+
+        supervisor.mark_ready("bash");
+
+        assert_eq!(supervisor.readiness("bash"), Readiness::Ready);
+    }
+
+    #[tokio::test]
+    async fn build_logs_are_streamed_until_the_build_finishes() {
+        let supervisor = Supervisor::default();
+        let container = supervisor.container("bash");
+
+        container.begin_build();
+        container.log_build("first");
+
+        assert_eq!(supervisor.readiness("bash"), Readiness::Building);
+
+        let (lines, receiver) = supervisor.build_log("bash");
+        let mut receiver = receiver.unwrap();
+
+        assert_eq!(lines, ["first"]);
+
+        container.log_build("second");
+        container.end_build();
+
+        assert_eq!(receiver.recv().await.unwrap(), "second");
+        assert!(receiver.recv().await.is_err());
+
+        let (lines, receiver) = supervisor.build_log("bash");
+
+        assert_eq!(lines, ["first", "second"]);
+        assert!(receiver.is_none());
+
+        let times = supervisor.build_times("bash");
+
+        assert!(times.started.is_some_and(|started| times.finished >= Some(started)));
+    }
+
+    #[tokio::test]
+    async fn failed_builds_are_reported_per_language() {
+        let supervisor = Supervisor::default();
+        let failed = supervisor.build_images(&[String::from("does-not-exist")], true).await;
+
+        assert_eq!(failed, ["does-not-exist"]);
+        assert!(matches!(supervisor.readiness("does-not-exist"), Readiness::Failed(_)));
+        assert!(supervisor.build_times("does-not-exist").finished.is_some());
+    }
+}
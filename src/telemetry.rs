@@ -0,0 +1,62 @@
+use anyhow::Result;
+use axum::http::{HeaderMap, HeaderName};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+
+use crate::config::Telemetry;
+
+/// Creates the provider exporting traces to the collector, or `None` when export is disabled.
+///
+/// # Errors
+///
+/// - When creating the exporter fails.
+pub fn init(config: &Telemetry) -> Result<Option<TracerProvider>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let exporter = SpanExporter::builder().with_tonic().with_endpoint(&config.endpoint).build()?;
+    let provider = TracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())]))
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .build();
+
+    global::set_tracer_provider(provider.clone());
+
+    Ok(Some(provider))
+}
+
+/// Returns the tracer spans are exported with.
+#[must_use]
+pub fn tracer(provider: &TracerProvider) -> Tracer {
+    provider.tracer(env!("CARGO_PKG_NAME"))
+}
+
+/// Returns the trace context of the caller from the `traceparent` and `tracestate` headers of
+/// a request, which is empty when they are absent or export is disabled.
+#[must_use]
+pub fn remote_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
@@ -1,4 +1,5 @@
 use std::process::{self, Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::Context;
 use guess_host_triple::guess_host_triple;
@@ -13,7 +14,8 @@ macro_rules! println_centered {
     };
 }
 
-pub fn check_if_docker_exists() -> Result<()> {
+/// Checks whether the Docker CLI is on the `PATH` and can reach the daemon.
+pub fn is_docker_available() -> Result<bool> {
     let cmd = Command::new("docker")
         .arg("version")
         .stdout(Stdio::null())
@@ -21,7 +23,11 @@ pub fn check_if_docker_exists() -> Result<()> {
         .stdin(Stdio::null())
         .status()?;
 
-    if !cmd.success() {
+    Ok(cmd.success())
+}
+
+pub fn check_if_docker_exists() -> Result<()> {
+    if !is_docker_available()? {
         println!(
             "The {} binary is missing. Maybe its missing on the {} environment variable?",
             "docker".bold().blue(),
@@ -34,6 +40,12 @@ pub fn check_if_docker_exists() -> Result<()> {
     Ok(())
 }
 
+/// Returns `time` in seconds since the Unix epoch, or `None` if it is before the epoch.
+#[must_use]
+pub fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|time| time.as_secs())
+}
+
 pub fn format_string_vec(arr: &[String]) -> String {
     match arr.len() {
         0 => String::new(),